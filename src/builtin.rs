@@ -0,0 +1,739 @@
+const MONTHS: [&str; 12] = [
+    "january",
+    "february",
+    "march",
+    "april",
+    "may",
+    "june",
+    "july",
+    "august",
+    "september",
+    "october",
+    "november",
+    "december",
+];
+
+const YES: [&str; 14] = [
+    "yes",
+    "yeah",
+    "yep",
+    "yup",
+    "sure",
+    "correct",
+    "right",
+    "true",
+    "ok",
+    "okay",
+    "affirmative",
+    "absolutely",
+    "certainly",
+    "indeed",
+];
+
+const NO: [&str; 8] = [
+    "no",
+    "nope",
+    "nah",
+    "wrong",
+    "incorrect",
+    "false",
+    "negative",
+    "never",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Mode {
+    Speech,
+    Dtmf,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Kind {
+    Transcribe,
+    Digits,
+    Boolean,
+    Number,
+    Date,
+    Currency,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BuiltinGrammar {
+    pub uri: String,
+    pub mode: Mode,
+    pub kind: Kind,
+    min_length: Option<usize>,
+    max_length: Option<usize>,
+}
+
+impl BuiltinGrammar {
+    /// Parses URIs like `builtin:speech/digits?minlength=3;maxlength=5`.
+    pub fn parse(uri: &str) -> Option<Self> {
+        let uri = uri.trim();
+        let rest = uri.strip_prefix("builtin:")?;
+        let (path, query) = rest.split_once('?').unwrap_or((rest, ""));
+        let (mode, kind) = path.split_once('/')?;
+        let mode = match mode.to_ascii_lowercase().as_str() {
+            "speech" | "grammar" => Mode::Speech,
+            "dtmf" => Mode::Dtmf,
+            _ => return None,
+        };
+        let kind = match kind.to_ascii_lowercase().as_str() {
+            "transcribe" | "dictation" => Kind::Transcribe,
+            "digits" => Kind::Digits,
+            "boolean" => Kind::Boolean,
+            "number" => Kind::Number,
+            "date" => Kind::Date,
+            "currency" => Kind::Currency,
+            _ => return None,
+        };
+        let mut grammar = Self {
+            uri: uri.to_owned(),
+            mode,
+            kind,
+            min_length: None,
+            max_length: None,
+        };
+        for param in query.split([';', '&']) {
+            let Some((name, value)) = param.split_once('=') else {
+                continue;
+            };
+            let Ok(value) = value.trim().parse::<usize>() else {
+                continue;
+            };
+            match name.trim().to_ascii_lowercase().as_str() {
+                "length" => {
+                    grammar.min_length = Some(value);
+                    grammar.max_length = Some(value);
+                }
+                "minlength" => grammar.min_length = Some(value),
+                "maxlength" => grammar.max_length = Some(value),
+                _ => {}
+            }
+        }
+        Some(grammar)
+    }
+
+    /// Normalizes the input into the grammar's structured value.
+    pub fn interpret(&self, input: &str) -> Option<String> {
+        match self.mode {
+            Mode::Speech => self.interpret_speech(input),
+            Mode::Dtmf => self.interpret_dtmf(input),
+        }
+    }
+
+    /// Largest number of DTMF keys this grammar accepts, if bounded.
+    pub fn max_length(&self) -> Option<usize> {
+        match self.kind {
+            Kind::Boolean => Some(1),
+            Kind::Date => Some(8),
+            _ => self.max_length,
+        }
+    }
+
+    fn interpret_speech(&self, input: &str) -> Option<String> {
+        let tokens = tokenize(input);
+        match self.kind {
+            Kind::Transcribe => {
+                let text = input.trim();
+                (!text.is_empty()).then(|| text.to_owned())
+            }
+            Kind::Digits => digits(&tokens).filter(|digits| self.length_matches(digits)),
+            Kind::Boolean => boolean(&tokens).map(|value| value.to_string()),
+            Kind::Number => number(&tokens),
+            Kind::Date => date(&tokens),
+            Kind::Currency => currency(&tokens),
+        }
+    }
+
+    fn interpret_dtmf(&self, input: &str) -> Option<String> {
+        let keys = input.trim();
+        if keys.is_empty() {
+            return None;
+        }
+        match self.kind {
+            Kind::Transcribe => Some(keys.to_owned()),
+            Kind::Digits => keys
+                .chars()
+                .all(|c| c.is_ascii_digit())
+                .then(|| keys.to_owned())
+                .filter(|digits| self.length_matches(digits)),
+            Kind::Boolean => match keys {
+                "1" => Some(true.to_string()),
+                "2" => Some(false.to_string()),
+                _ => None,
+            },
+            Kind::Number | Kind::Currency => dtmf_decimal(keys),
+            Kind::Date => {
+                if keys.len() != 8 || !keys.chars().all(|c| c.is_ascii_digit()) {
+                    return None;
+                }
+                let year = keys[0..4].parse().ok()?;
+                let month = keys[4..6].parse().ok()?;
+                let day = keys[6..8].parse().ok()?;
+                iso_date(Some(year), month, day)
+            }
+        }
+    }
+
+    fn length_matches(&self, digits: &str) -> bool {
+        let length = digits.len();
+        !matches!(self.min_length, Some(min) if length < min)
+            && !matches!(self.max_length, Some(max) if length > max)
+    }
+}
+
+fn tokenize(input: &str) -> Vec<String> {
+    input
+        .to_lowercase()
+        .replace(['-', ',', '!', '?'], " ")
+        .split_whitespace()
+        .map(|token| token.trim_end_matches('.').to_owned())
+        .filter(|token| !token.is_empty())
+        .collect()
+}
+
+fn digit_word(token: &str) -> Option<char> {
+    let digit = match token {
+        "zero" | "oh" | "o" | "nought" => '0',
+        "one" => '1',
+        "two" => '2',
+        "three" => '3',
+        "four" => '4',
+        "five" => '5',
+        "six" => '6',
+        "seven" => '7',
+        "eight" => '8',
+        "nine" => '9',
+        _ => return None,
+    };
+    Some(digit)
+}
+
+fn digits(tokens: &[String]) -> Option<String> {
+    let mut digits = String::new();
+    let mut repeat = 1;
+    for token in tokens {
+        match token.as_str() {
+            "double" => repeat = 2,
+            "triple" => repeat = 3,
+            token if !token.is_empty() && token.chars().all(|c| c.is_ascii_digit()) => {
+                digits.push_str(token);
+                repeat = 1;
+            }
+            token => {
+                let digit = digit_word(token)?;
+                for _ in 0..repeat {
+                    digits.push(digit);
+                }
+                repeat = 1;
+            }
+        }
+    }
+    (!digits.is_empty()).then_some(digits)
+}
+
+fn boolean(tokens: &[String]) -> Option<bool> {
+    let yes = tokens.iter().any(|token| YES.contains(&token.as_str()));
+    let no = tokens.iter().any(|token| NO.contains(&token.as_str()));
+    match (yes, no) {
+        (true, false) => Some(true),
+        (false, true) => Some(false),
+        _ => None,
+    }
+}
+
+fn unit_value(token: &str) -> Option<u64> {
+    let value = match token {
+        "zero" | "oh" => 0,
+        "a" | "one" => 1,
+        "two" => 2,
+        "three" => 3,
+        "four" => 4,
+        "five" => 5,
+        "six" => 6,
+        "seven" => 7,
+        "eight" => 8,
+        "nine" => 9,
+        "ten" => 10,
+        "eleven" => 11,
+        "twelve" => 12,
+        "thirteen" => 13,
+        "fourteen" => 14,
+        "fifteen" => 15,
+        "sixteen" => 16,
+        "seventeen" => 17,
+        "eighteen" => 18,
+        "nineteen" => 19,
+        "twenty" => 20,
+        "thirty" => 30,
+        "forty" => 40,
+        "fifty" => 50,
+        "sixty" => 60,
+        "seventy" => 70,
+        "eighty" => 80,
+        "ninety" => 90,
+        _ => return None,
+    };
+    Some(value)
+}
+
+fn ordinal_value(token: &str) -> Option<u64> {
+    let value = match token {
+        "first" => 1,
+        "second" => 2,
+        "third" => 3,
+        "fourth" => 4,
+        "fifth" => 5,
+        "sixth" => 6,
+        "seventh" => 7,
+        "eighth" => 8,
+        "ninth" => 9,
+        "tenth" => 10,
+        "eleventh" => 11,
+        "twelfth" => 12,
+        "thirteenth" => 13,
+        "fourteenth" => 14,
+        "fifteenth" => 15,
+        "sixteenth" => 16,
+        "seventeenth" => 17,
+        "eighteenth" => 18,
+        "nineteenth" => 19,
+        "twentieth" => 20,
+        "thirtieth" => 30,
+        _ => {
+            let numeric = token.trim_end_matches(|c: char| c.is_ascii_alphabetic());
+            if numeric.len() == token.len() || numeric.is_empty() {
+                return None;
+            }
+            return numeric.parse().ok();
+        }
+    };
+    Some(value)
+}
+
+/// Spoken cardinal number, e.g. "one hundred and twenty three". Numbers
+/// only add up after a multiplier or as tens and a unit, so "one two" is
+/// not a number.
+fn cardinal(tokens: &[String]) -> Option<u64> {
+    if tokens.is_empty() {
+        return None;
+    }
+    let mut total = 0u64;
+    let mut current = 0u64;
+    let mut seen = false;
+    // Whether a number may follow without joining one before it.
+    let mut fresh = true;
+    for token in tokens {
+        let token = token.as_str();
+        if token == "and" {
+            continue;
+        }
+        if let Some(value) = token.parse::<u64>().ok().or_else(|| unit_value(token)) {
+            let joins = fresh
+                || (current.is_multiple_of(100) && value < 100)
+                || (current.is_multiple_of(10) && current % 100 >= 20 && value < 10);
+            if !joins {
+                return None;
+            }
+            current = current.checked_add(value)?;
+            fresh = false;
+        } else {
+            let scale = match token {
+                "hundred" => 100,
+                "thousand" => 1_000,
+                "million" => 1_000_000,
+                "billion" => 1_000_000_000,
+                _ => return None,
+            };
+            let base = if current == 0 { 1 } else { current };
+            if scale == 100 {
+                current = base.checked_mul(scale)?;
+            } else {
+                total = total.checked_add(base.checked_mul(scale)?)?;
+                current = 0;
+                fresh = true;
+            }
+        }
+        seen = true;
+    }
+    total.checked_add(current).filter(|_| seen)
+}
+
+fn number(tokens: &[String]) -> Option<String> {
+    let (negative, tokens) = match tokens.first().map(String::as_str) {
+        Some("minus" | "negative") => (true, &tokens[1..]),
+        _ => (false, tokens),
+    };
+    if let [value] = tokens {
+        if is_decimal(value) {
+            return Some(if negative {
+                format!("-{}", value)
+            } else {
+                value.to_owned()
+            });
+        }
+    }
+    let point = tokens.iter().position(|token| token == "point");
+    let (whole, fraction) = match point {
+        Some(index) => (&tokens[..index], Some(&tokens[index + 1..])),
+        None => (tokens, None),
+    };
+    let whole = if whole.is_empty() && fraction.is_some() {
+        0
+    } else {
+        cardinal(whole)?
+    };
+    let mut value = whole.to_string();
+    if let Some(fraction) = fraction {
+        value.push('.');
+        value.push_str(&digits(fraction)?);
+    }
+    if negative {
+        value.insert(0, '-');
+    }
+    Some(value)
+}
+
+fn month(token: &str) -> Option<u32> {
+    MONTHS
+        .iter()
+        .position(|month| *month == token || (token.len() >= 3 && month.starts_with(token)))
+        .map(|index| index as u32 + 1)
+}
+
+fn day(tokens: &[String]) -> Option<u32> {
+    let day = match tokens {
+        [token] => ordinal_value(token).or_else(|| cardinal(tokens))?,
+        [tens, unit] => {
+            let tens = unit_value(tens).filter(|tens| *tens >= 20)?;
+            let unit = ordinal_value(unit)
+                .or_else(|| unit_value(unit))
+                .filter(|unit| *unit < 10)?;
+            tens + unit
+        }
+        _ => return None,
+    };
+    (1..=31).contains(&day).then_some(day as u32)
+}
+
+/// Spoken year: "2024", "twenty twenty four", "two thousand and five".
+fn year(tokens: &[String]) -> Option<u32> {
+    if tokens.iter().any(|token| token == "thousand") {
+        return cardinal(tokens).and_then(|year| u32::try_from(year).ok());
+    }
+    if let [token] = tokens {
+        return token.parse().ok();
+    }
+    for split in 1..tokens.len() {
+        let century = cardinal(&tokens[..split]);
+        let rest = cardinal(&tokens[split..]);
+        if let (Some(century), Some(rest)) = (century, rest) {
+            if (10..100).contains(&century) && rest < 100 {
+                return u32::try_from(century * 100 + rest).ok();
+            }
+        }
+    }
+    None
+}
+
+fn date(tokens: &[String]) -> Option<String> {
+    if let [token] = tokens {
+        if let Some(date) = numeric_date(token) {
+            return Some(date);
+        }
+    }
+    let tokens = tokens
+        .iter()
+        .filter(|token| !matches!(token.as_str(), "the" | "of"))
+        .cloned()
+        .collect::<Vec<_>>();
+    let position = tokens.iter().position(|token| month(token).is_some())?;
+    let month = month(&tokens[position])?;
+    let (day, year) = if position == 0 {
+        let rest = &tokens[1..];
+        let split = (1..=rest.len().min(2))
+            .rev()
+            .find(|split| day(&rest[..*split]).is_some())?;
+        (day(&rest[..split])?, &rest[split..])
+    } else {
+        (day(&tokens[..position])?, &tokens[position + 1..])
+    };
+    let year = if year.is_empty() {
+        None
+    } else {
+        Some(self::year(year)?)
+    };
+    iso_date(year, month, day)
+}
+
+fn numeric_date(token: &str) -> Option<String> {
+    let parts = token
+        .split(['-', '/'])
+        .map(|part| part.parse::<u32>().ok())
+        .collect::<Option<Vec<_>>>()?;
+    match parts.as_slice() {
+        [year, month, day] if token.contains('-') => iso_date(Some(*year), *month, *day),
+        [month, day, year] => iso_date(Some(*year), *month, *day),
+        _ => None,
+    }
+}
+
+fn iso_date(year: Option<u32>, month: u32, day: u32) -> Option<String> {
+    let leap = year.is_none_or(leap_year);
+    let days = match month {
+        2 if leap => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        1..=12 => 31,
+        _ => return None,
+    };
+    if !(1..=days).contains(&day) {
+        return None;
+    }
+    Some(match year {
+        Some(year) => format!("{:04}-{:02}-{:02}", year, month, day),
+        None => format!("--{:02}-{:02}", month, day),
+    })
+}
+
+fn leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+fn currency_code(token: &str) -> Option<&'static str> {
+    let code = match token {
+        "dollar" | "dollars" | "buck" | "bucks" | "usd" => "USD",
+        "euro" | "euros" | "eur" => "EUR",
+        "pound" | "pounds" | "gbp" => "GBP",
+        "ruble" | "rubles" | "roubles" | "rub" => "RUB",
+        _ => return None,
+    };
+    Some(code)
+}
+
+fn currency(tokens: &[String]) -> Option<String> {
+    if let [token] = tokens {
+        for (symbol, code) in [('$', "USD"), ('€', "EUR"), ('£', "GBP")] {
+            if let Some(amount) = token.strip_prefix(symbol) {
+                if !is_decimal(amount) || amount.starts_with('-') {
+                    return None;
+                }
+                return Some(format!("{}{}", code, amount));
+            }
+        }
+    }
+    let unit = tokens
+        .iter()
+        .position(|token| currency_code(token).is_some());
+    let cents = tokens
+        .iter()
+        .position(|token| matches!(token.as_str(), "cent" | "cents" | "penny" | "pence"));
+    let (code, major, minor) = match (unit, cents) {
+        (Some(unit), Some(cents)) if unit < cents => (
+            currency_code(&tokens[unit]),
+            cardinal(&tokens[..unit])?,
+            cardinal(&tokens[unit + 1..cents])?,
+        ),
+        (Some(unit), None) => {
+            let major = &tokens[..unit];
+            let rest = &tokens[unit + 1..];
+            let minor = if rest.is_empty() { 0 } else { cardinal(rest)? };
+            match major.iter().position(|token| token == "point") {
+                Some(_) => {
+                    let amount = number(major)?;
+                    return Some(format!("{}{}", currency_code(&tokens[unit])?, amount));
+                }
+                None => (currency_code(&tokens[unit]), cardinal(major)?, minor),
+            }
+        }
+        (None, Some(cents)) => (None, 0, cardinal(&tokens[..cents])?),
+        (None, None) => return number(tokens),
+        _ => return None,
+    };
+    if minor >= 100 {
+        return None;
+    }
+    Some(format!(
+        "{}{}.{:02}",
+        code.unwrap_or_default(),
+        major,
+        minor
+    ))
+}
+
+/// Plain `[-]digits[.digits]` number.
+fn is_decimal(value: &str) -> bool {
+    let value = value.strip_prefix('-').unwrap_or(value);
+    let (whole, fraction) = value.split_once('.').unwrap_or((value, "0"));
+    let digits = |part: &str| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit());
+    digits(whole) && digits(fraction)
+}
+
+/// DTMF decimal entry where `*` stands for the decimal point.
+fn dtmf_decimal(keys: &str) -> Option<String> {
+    let value = keys.replacen('*', ".", 1);
+    let valid = !value.is_empty()
+        && value.chars().all(|c| c.is_ascii_digit() || c == '.')
+        && value.chars().any(|c| c.is_ascii_digit());
+    valid.then_some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(uri: &str, cases: &[(&str, Option<&str>)]) {
+        let grammar = BuiltinGrammar::parse(uri).unwrap();
+        for (input, expected) in cases {
+            assert_eq!(
+                grammar.interpret(input).as_deref(),
+                *expected,
+                "{:?}",
+                input
+            );
+        }
+    }
+
+    #[test]
+    fn parses_uris() {
+        let grammar =
+            BuiltinGrammar::parse(" builtin:dtmf/digits?minlength=3;maxlength=5").unwrap();
+        assert_eq!((grammar.mode, grammar.kind), (Mode::Dtmf, Kind::Digits));
+        assert_eq!((grammar.min_length, grammar.max_length), (Some(3), Some(5)));
+        let grammar = BuiltinGrammar::parse("builtin:grammar/Number?length=2&x=y").unwrap();
+        assert_eq!((grammar.mode, grammar.kind), (Mode::Speech, Kind::Number));
+        assert_eq!(grammar.max_length(), Some(2));
+        assert!(BuiltinGrammar::parse("builtin:speech/color").is_none());
+        assert!(BuiltinGrammar::parse("builtin:video/digits").is_none());
+        assert!(BuiltinGrammar::parse("http://example.com/digits").is_none());
+    }
+
+    #[test]
+    fn transcribe() {
+        check(
+            "builtin:speech/transcribe",
+            &[(" Hello there ", Some("Hello there")), ("  ", None)],
+        );
+        check(
+            "builtin:dtmf/transcribe",
+            &[("12#", Some("12#")), ("", None)],
+        );
+    }
+
+    #[test]
+    fn digits() {
+        check(
+            "builtin:speech/digits?minlength=3;maxlength=5",
+            &[
+                ("one two three", Some("123")),
+                ("double five oh", Some("550")),
+                ("triple seven 9", Some("7779")),
+                ("four 2", None),
+                ("one two three four five six", None),
+                ("one two many", None),
+            ],
+        );
+        check(
+            "builtin:dtmf/digits?length=4",
+            &[("1234", Some("1234")), ("123", None), ("12*4", None)],
+        );
+    }
+
+    #[test]
+    fn boolean() {
+        check(
+            "builtin:speech/boolean",
+            &[
+                ("Yes, please", Some("true")),
+                ("nope", Some("false")),
+                ("yes no", None),
+                ("maybe", None),
+            ],
+        );
+        check(
+            "builtin:dtmf/boolean",
+            &[("1", Some("true")), ("2", Some("false")), ("3", None)],
+        );
+    }
+
+    #[test]
+    fn number() {
+        check(
+            "builtin:speech/number",
+            &[
+                ("forty two", Some("42")),
+                ("one hundred and twenty three", Some("123")),
+                ("a thousand", Some("1000")),
+                ("two thousand five", Some("2005")),
+                ("five hundred thousand", Some("500000")),
+                ("minus seven", Some("-7")),
+                ("three point one four", Some("3.14")),
+                ("point five", Some("0.5")),
+                ("12.5", Some("12.5")),
+                ("one two three", None),
+                ("twenty twenty four", None),
+                ("ten five", None),
+                ("nan", None),
+                ("inf", None),
+                ("1e5", None),
+                ("12.", Some("12")),
+                ("20000000000 billion", None),
+            ],
+        );
+        check(
+            "builtin:dtmf/number",
+            &[("12*5", Some("12.5")), ("*", None), ("1#", None)],
+        );
+    }
+
+    #[test]
+    fn date() {
+        check(
+            "builtin:speech/date",
+            &[
+                ("march fifth twenty twenty four", Some("2024-03-05")),
+                ("the 21st of june 1999", Some("1999-06-21")),
+                ("december twenty fifth", Some("--12-25")),
+                ("feb 29 2024", Some("2024-02-29")),
+                ("february twenty ninth two thousand and twenty three", None),
+                ("february twenty ninth", Some("--02-29")),
+                ("february 29 1900", None),
+                ("february 29 2000", Some("2000-02-29")),
+                ("april thirty first", None),
+                ("2024-02-30", None),
+                ("12/01/2024", Some("2024-12-01")),
+                ("yesterday", None),
+            ],
+        );
+        check(
+            "builtin:dtmf/date",
+            &[
+                ("20240229", Some("2024-02-29")),
+                ("20230229", None),
+                ("2024022", None),
+            ],
+        );
+    }
+
+    #[test]
+    fn currency() {
+        check(
+            "builtin:speech/currency",
+            &[
+                ("five dollars and twenty cents", Some("USD5.20")),
+                ("ten euros fifty", Some("EUR10.50")),
+                ("three point five pounds", Some("GBP3.5")),
+                ("ninety nine cents", Some("0.99")),
+                ("$12.99", Some("USD12.99")),
+                ("€7", Some("EUR7")),
+                ("$nan", None),
+                ("$1e5", None),
+                ("$-5", None),
+                ("five dollars two hundred cents", None),
+                ("forty two", Some("42")),
+            ],
+        );
+        check("builtin:dtmf/currency", &[("5*25", Some("5.25"))]);
+    }
+}
@@ -0,0 +1,609 @@
+use crate::config_file;
+use crate::fetch::{self, FetchPolicy};
+use crate::fuzzy::{self, FuzzyMatcher};
+use crate::language::LanguageRoutes;
+use crate::srgs;
+use std::collections::{BTreeMap, HashMap};
+use std::str::FromStr;
+use std::sync::{Arc, PoisonError, RwLock};
+
+/// Engine params read elsewhere rather than by `EngineConfig`.
+const ENGINE_PARAMS: [&str; 3] = [
+    "filename",
+    config_file::CONFIG_FILE_PARAM,
+    config_file::POLL_INTERVAL_PARAM,
+];
+/// Params `EngineConfig` knows.
+const CONFIG_PARAMS: [&str; 20] = [
+    "result-format",
+    "inband-dtmf",
+    "dictation-sink",
+    "waveform-dir",
+    "fuzzy-match",
+    "fuzzy-threshold",
+    "synonyms",
+    "default-language",
+    "backends",
+    "language-fallbacks",
+    "speech-timeout",
+    "energy-threshold",
+    "pre-roll",
+    "max-hints",
+    "fetch-timeout",
+    "fetch-roots",
+    "fetch-max-bytes",
+    "fetch-allow-hosts",
+    "fetch-deny-hosts",
+    "profile-attrib",
+];
+/// Prefix of the params of a profile, `profile.<name>.<param>`.
+const PROFILE_PREFIX: &str = "profile.";
+/// Profile param with the channel attributes that select the profile.
+const PROFILE_MATCH: &str = "match";
+/// Channel attribute naming the profile of the channel outright, if the
+/// `profile-attrib` param allows it.
+const PROFILE_ATTRIB: &str = "profile";
+const DEFAULT_SPEECH_TIMEOUT: usize = 100;
+/// Most milliseconds of pre-roll a request or the engine may ask for.
+pub const MAX_PRE_ROLL: usize = 2000;
+const MAX_SPEECH_TIMEOUT: usize = 10000;
+const DEFAULT_MAX_HINTS: usize = 500;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum ResultFormat {
+    #[default]
+    Nlsml,
+    Text,
+}
+
+impl ResultFormat {
+    fn parse(value: &str) -> Option<Self> {
+        match value.trim().to_ascii_lowercase().as_str() {
+            "nlsml" | "application/nlsml+xml" => Some(Self::Nlsml),
+            "text" | "text/plain" => Some(Self::Text),
+            _ => None,
+        }
+    }
+}
+
+/// Speech detector settings of requests that do not tune them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DetectorConfig {
+    /// Milliseconds of activity before speech is detected.
+    pub speech_timeout: usize,
+    pub energy_threshold: Option<usize>,
+    /// Milliseconds of audio before the detected speech sent along with it.
+    pub pre_roll: usize,
+}
+
+impl Default for DetectorConfig {
+    fn default() -> Self {
+        Self {
+            speech_timeout: DEFAULT_SPEECH_TIMEOUT,
+            energy_threshold: None,
+            pre_roll: 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Limits {
+    /// Phrases passed to the backend at most.
+    pub max_hints: usize,
+    /// Fetch-Timeout of requests without one.
+    pub fetch_timeout: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            max_hints: DEFAULT_MAX_HINTS,
+            fetch_timeout: fetch::DEFAULT_FETCH_TIMEOUT,
+        }
+    }
+}
+
+/// Named config of the channels whose attributes select it.
+#[derive(Debug, Clone)]
+pub struct Profile {
+    pub name: String,
+    /// Attribute names with `*` patterns of their values, all to match.
+    conditions: Vec<(String, String)>,
+    pub config: Arc<EngineConfig>,
+}
+
+impl Profile {
+    fn matches(&self, attribs: &HashMap<String, String>) -> bool {
+        !self.conditions.is_empty()
+            && self.conditions.iter().all(|(name, pattern)| {
+                attribs.iter().any(|(attrib, value)| {
+                    attrib.eq_ignore_ascii_case(name) && glob(pattern, value)
+                })
+            })
+    }
+}
+
+/// Parses `x-tenant=acme, x-line=*-support` conditions.
+fn parse_conditions(value: &str) -> Result<Vec<(String, String)>, String> {
+    value
+        .split(',')
+        .filter(|condition| !condition.trim().is_empty())
+        .map(|condition| {
+            condition
+                .split_once('=')
+                .map(|(name, pattern)| (name.trim().to_owned(), pattern.trim().to_owned()))
+                .filter(|(name, _)| !name.is_empty())
+                .ok_or_else(|| {
+                    format!("Invalid condition {:?}, expected attrib=pattern", condition)
+                })
+        })
+        .collect()
+}
+
+/// Case-insensitive match of `text` to a pattern where `*` stands for any
+/// text. Addresses match without their angle brackets.
+fn glob(pattern: &str, text: &str) -> bool {
+    let text = text.trim().trim_start_matches('<').trim_end_matches('>');
+    let (pattern, text) = (pattern.to_lowercase(), text.to_lowercase());
+    let parts = pattern.split('*').collect::<Vec<_>>();
+    let (first, last) = (parts[0], parts[parts.len() - 1]);
+    if parts.len() == 1 {
+        return text == pattern;
+    }
+    if text.len() < first.len() + last.len() || !text.starts_with(first) || !text.ends_with(last) {
+        return false;
+    }
+    let mut rest = &text[first.len()..text.len() - last.len()];
+    for part in &parts[1..parts.len() - 1] {
+        match rest.find(part) {
+            Some(i) => rest = &rest[i + part.len()..],
+            None => return false,
+        }
+    }
+    true
+}
+
+#[derive(Debug, Clone)]
+pub struct EngineConfig {
+    pub result_format: ResultFormat,
+    pub inband_dtmf: bool,
+    /// File every dictation segment is appended to.
+    pub dictation_sink: Option<String>,
+    /// Directory of the waveforms saved for Save-Waveform.
+    pub waveform_dir: String,
+    /// Matcher of transcripts that miss grammar phrases, if enabled.
+    pub fuzzy: Option<FuzzyMatcher>,
+    /// Backends of the Speech-Language of requests.
+    pub languages: LanguageRoutes,
+    pub detector: DetectorConfig,
+    pub limits: Limits,
+    pub fetch: FetchPolicy,
+    /// Profiles by name, each with the config of its channels.
+    pub profiles: Vec<Profile>,
+    /// Whether the `profile` channel attribute may select a profile
+    /// regardless of its conditions.
+    pub profile_attrib: bool,
+}
+
+impl Default for EngineConfig {
+    fn default() -> Self {
+        Self {
+            result_format: ResultFormat::default(),
+            inband_dtmf: true,
+            dictation_sink: None,
+            waveform_dir: String::from("."),
+            fuzzy: None,
+            languages: LanguageRoutes::default(),
+            detector: DetectorConfig::default(),
+            limits: Limits::default(),
+            fetch: FetchPolicy::default(),
+            profiles: vec![],
+            profile_attrib: false,
+        }
+    }
+}
+
+pub fn parse_bool(value: &str) -> Option<bool> {
+    match value.trim().to_ascii_lowercase().as_str() {
+        "true" | "yes" | "on" | "1" => Some(true),
+        "false" | "no" | "off" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parses `too=two, for=four` into words and their canonical form.
+fn parse_synonyms(value: &str) -> Result<HashMap<String, String>, String> {
+    let mut synonyms = HashMap::new();
+    for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+        let (word, canonical) = pair
+            .split_once('=')
+            .map(|(word, canonical)| (srgs::normalize(word), srgs::normalize(canonical)))
+            .filter(|(word, canonical)| !word.is_empty() && !canonical.is_empty())
+            .ok_or_else(|| format!("Invalid synonym {:?}, expected word=canonical", pair))?;
+        synonyms.insert(word, canonical);
+    }
+    Ok(synonyms)
+}
+
+/// Engine params with validation of their values.
+struct Params<'a>(&'a HashMap<String, String>);
+
+impl Params<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        self.0.get(name).map(|value| value.trim())
+    }
+
+    fn parse<T>(
+        &self,
+        name: &str,
+        expected: &str,
+        parse: impl FnOnce(&str) -> Option<T>,
+    ) -> Result<Option<T>, String> {
+        let Some(value) = self.get(name) else {
+            return Ok(None);
+        };
+        match parse(value) {
+            Some(parsed) => Ok(Some(parsed)),
+            None => Err(format!(
+                "Invalid {} {:?}, expected {}",
+                name, value, expected
+            )),
+        }
+    }
+
+    /// Comma-separated items, none if unset.
+    fn list(&self, name: &str) -> Vec<String> {
+        self.get(name)
+            .unwrap_or_default()
+            .split(',')
+            .map(str::trim)
+            .filter(|item| !item.is_empty())
+            .map(str::to_owned)
+            .collect()
+    }
+
+    fn bool(&self, name: &str) -> Result<Option<bool>, String> {
+        self.parse(name, "true or false", parse_bool)
+    }
+
+    /// Number in `min..=max`.
+    fn number<T: FromStr + PartialOrd + std::fmt::Display>(
+        &self,
+        name: &str,
+        min: T,
+        max: T,
+    ) -> Result<Option<T>, String> {
+        let expected = format!("a number from {} to {}", min, max);
+        self.parse(name, &expected, |value| {
+            value
+                .parse()
+                .ok()
+                .filter(|number| *number >= min && *number <= max)
+        })
+    }
+}
+
+impl EngineConfig {
+    /// Config of the `<engine>` params. Any invalid value is an error.
+    /// Every profile overrides the params it sets.
+    pub fn new(params: &HashMap<String, String>) -> Result<Self, String> {
+        let mut base = HashMap::new();
+        let mut profiles = BTreeMap::<String, HashMap<String, String>>::new();
+        for (name, value) in params {
+            let Some(profile_param) = name.strip_prefix(PROFILE_PREFIX) else {
+                base.insert(name.clone(), value.clone());
+                continue;
+            };
+            let (profile, param) = profile_param
+                .split_once('.')
+                .filter(|(profile, param)| !profile.is_empty() && !param.is_empty())
+                .ok_or_else(|| format!("Invalid profile param {:?}", name))?;
+            let profile = profiles.entry(profile.to_owned()).or_default();
+            profile.insert(param.to_owned(), value.clone());
+        }
+        let mut config = Self::parse(&base)?;
+        for (name, mut params) in profiles {
+            let conditions = match params.remove(PROFILE_MATCH) {
+                Some(value) => parse_conditions(&value),
+                None => Ok(vec![]),
+            };
+            let mut merged = base.clone();
+            merged.extend(params);
+            let profile = conditions
+                .and_then(|conditions| {
+                    let config = Arc::new(Self::parse(&merged)?);
+                    Ok(Profile {
+                        name: name.clone(),
+                        conditions,
+                        config,
+                    })
+                })
+                .map_err(|e| format!("Profile {:?}: {}", name, e))?;
+            config.profiles.push(profile);
+        }
+        Ok(config)
+    }
+
+    /// Name of the profile the channel attributes select: the one the
+    /// `profile` attribute names if `profile-attrib` is on, else the first
+    /// whose conditions match.
+    pub fn select_profile(&self, attribs: &HashMap<String, String>) -> Option<&str> {
+        let named = attribs
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(PROFILE_ATTRIB))
+            .map(|(_, value)| value.trim());
+        if named.is_some() && !self.profile_attrib {
+            log::warn!(
+                "Ignore the {} attribute without profile-attrib",
+                PROFILE_ATTRIB
+            );
+        } else if let Some(named) = named {
+            match self.profiles.iter().find(|profile| profile.name == named) {
+                Some(profile) => return Some(&profile.name),
+                None => log::warn!("Unknown profile {:?}", named),
+            }
+        }
+        self.profiles
+            .iter()
+            .find(|profile| profile.matches(attribs))
+            .map(|profile| profile.name.as_str())
+    }
+
+    /// Config of the profile `name`, this one without it.
+    pub fn profiled(self: &Arc<Self>, name: Option<&str>) -> Arc<Self> {
+        let Some(name) = name else {
+            return self.clone();
+        };
+        match self.profiles.iter().find(|profile| profile.name == name) {
+            Some(profile) => profile.config.clone(),
+            None => {
+                log::warn!("Profile {:?} is gone, using the engine config", name);
+                self.clone()
+            }
+        }
+    }
+
+    fn parse(params: &HashMap<String, String>) -> Result<Self, String> {
+        for name in params.keys() {
+            if !CONFIG_PARAMS.contains(&name.as_str()) && !ENGINE_PARAMS.contains(&name.as_str()) {
+                log::warn!("Unknown engine param {:?}", name);
+            }
+        }
+        let params = Params(params);
+        let mut config = Self::default();
+        if let Some(format) = params.parse("result-format", "nlsml or text", ResultFormat::parse)? {
+            config.result_format = format;
+        }
+        if let Some(enabled) = params.bool("inband-dtmf")? {
+            config.inband_dtmf = enabled;
+        }
+        config.dictation_sink = params
+            .get("dictation-sink")
+            .filter(|path| !path.is_empty())
+            .map(str::to_owned);
+        if let Some(dir) = params.get("waveform-dir") {
+            if dir.is_empty() {
+                return Err(String::from("Empty waveform-dir"));
+            }
+            config.waveform_dir = dir.to_owned();
+        }
+
+        if params.bool("fuzzy-match")?.unwrap_or(false) {
+            let threshold = params
+                .parse("fuzzy-threshold", "a number above 0 up to 1", |value| {
+                    value
+                        .parse::<f32>()
+                        .ok()
+                        .filter(|threshold| *threshold > 0.0 && *threshold <= 1.0)
+                })?
+                .unwrap_or(fuzzy::DEFAULT_THRESHOLD);
+            let synonyms = match params.get("synonyms") {
+                Some(value) => parse_synonyms(value)?,
+                None => HashMap::new(),
+            };
+            config.fuzzy = Some(FuzzyMatcher::new(threshold, synonyms));
+        } else if params.get("fuzzy-threshold").is_some() || params.get("synonyms").is_some() {
+            log::warn!("fuzzy-threshold and synonyms take effect with fuzzy-match only");
+        }
+
+        config.languages = LanguageRoutes::new(
+            params.get("default-language"),
+            params.get("backends"),
+            params.get("language-fallbacks"),
+        )?;
+
+        if let Some(timeout) = params.number("speech-timeout", 1, MAX_SPEECH_TIMEOUT)? {
+            config.detector.speech_timeout = timeout;
+        }
+        config.detector.energy_threshold = params.number("energy-threshold", 1, usize::MAX)?;
+        if let Some(duration) = params.number("pre-roll", 0, MAX_PRE_ROLL)? {
+            config.detector.pre_roll = duration;
+        }
+
+        if let Some(max_hints) = params.number("max-hints", 0, usize::MAX)? {
+            config.limits.max_hints = max_hints;
+        }
+        if let Some(timeout) = params.number("fetch-timeout", 1, usize::MAX)? {
+            config.limits.fetch_timeout = timeout;
+        }
+        config.fetch.roots = params
+            .list("fetch-roots")
+            .into_iter()
+            .map(From::from)
+            .collect();
+        if let Some(max_bytes) = params.number("fetch-max-bytes", 1, usize::MAX)? {
+            config.fetch.max_bytes = max_bytes;
+        }
+        config.fetch.allow_hosts = params.list("fetch-allow-hosts");
+        config.fetch.deny_hosts = params.list("fetch-deny-hosts");
+        if let Some(enabled) = params.bool("profile-attrib")? {
+            config.profile_attrib = enabled;
+        }
+        Ok(config)
+    }
+}
+
+/// Current config of the engine, replaced when the config file changes.
+/// Holders of a snapshot keep it as it was.
+#[derive(Debug, Clone)]
+pub struct ConfigStore(Arc<RwLock<Arc<EngineConfig>>>);
+
+impl ConfigStore {
+    pub fn leaked(config: EngineConfig) -> *mut Self {
+        Box::into_raw(Box::new(Self(Arc::new(RwLock::new(Arc::new(config))))))
+    }
+
+    pub unsafe fn destroy(this: *mut Self) {
+        if !this.is_null() {
+            drop(Box::from_raw(this));
+        }
+    }
+
+    pub fn snapshot(&self) -> Arc<EngineConfig> {
+        self.0
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    pub fn replace(&self, config: EngineConfig) {
+        *self.0.write().unwrap_or_else(PoisonError::into_inner) = Arc::new(config);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn error(pairs: &[(&str, &str)]) -> String {
+        EngineConfig::new(&params(pairs)).unwrap_err()
+    }
+
+    #[test]
+    fn defaults_without_params() {
+        let config = EngineConfig::new(&HashMap::new()).unwrap();
+        assert_eq!(config.result_format, ResultFormat::Nlsml);
+        assert!(config.inband_dtmf);
+        assert!(config.fuzzy.is_none());
+        assert_eq!(config.detector, DetectorConfig::default());
+        assert_eq!(config.limits, Limits::default());
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        for pairs in [
+            [("result-format", "json")],
+            [("inband-dtmf", "maybe")],
+            [("waveform-dir", " ")],
+            [("speech-timeout", "0")],
+            [("speech-timeout", "10001")],
+            [("energy-threshold", "0")],
+            [("pre-roll", "2001")],
+            [("pre-roll", "-1")],
+            [("max-hints", "many")],
+            [("fetch-timeout", "0")],
+            [("fetch-max-bytes", "0")],
+            [("profile-attrib", "sometimes")],
+            [("default-language", " ")],
+            [("backends", "en-US")],
+        ] {
+            assert!(EngineConfig::new(&params(&pairs)).is_err(), "{:?}", pairs);
+        }
+    }
+
+    #[test]
+    fn rejects_invalid_fuzzy_params() {
+        let fuzzy = ("fuzzy-match", "true");
+        assert!(error(&[fuzzy, ("fuzzy-threshold", "0")]).contains("fuzzy-threshold"));
+        assert!(error(&[fuzzy, ("fuzzy-threshold", "1.5")]).contains("fuzzy-threshold"));
+        assert!(error(&[fuzzy, ("synonyms", "too")]).contains("Invalid synonym"));
+        assert!(error(&[fuzzy, ("synonyms", "=two")]).contains("Invalid synonym"));
+        // Ignored without fuzzy-match.
+        assert!(EngineConfig::new(&params(&[("synonyms", "too")])).is_ok());
+    }
+
+    #[test]
+    fn rejects_invalid_profiles() {
+        assert!(error(&[("profile..pre-roll", "100")]).contains("Invalid profile param"));
+        assert!(error(&[("profile.acme", "100")]).contains("Invalid profile param"));
+        assert!(error(&[("profile.acme.match", "x-tenant")]).contains("Profile \"acme\""));
+        assert!(error(&[("profile.acme.pre-roll", "9999")]).contains("Profile \"acme\""));
+    }
+
+    #[test]
+    fn profiles_override_base_params() {
+        let config = EngineConfig::new(&params(&[
+            ("pre-roll", "100"),
+            ("max-hints", "10"),
+            ("profile.acme.pre-roll", "300"),
+        ]))
+        .unwrap();
+        let profile = &config.profiles[0].config;
+        assert_eq!(profile.detector.pre_roll, 300);
+        assert_eq!(profile.limits.max_hints, 10);
+        assert_eq!(config.detector.pre_roll, 100);
+    }
+
+    #[test]
+    fn selects_profile_by_conditions() {
+        let base = [
+            ("profile.acme.match", "x-tenant=acme, x-line=*-support"),
+            ("profile.other.pre-roll", "100"),
+        ];
+        let config = EngineConfig::new(&params(&base)).unwrap();
+        let acme = params(&[("X-Tenant", "ACME"), ("x-line", "de-support")]);
+        assert_eq!(config.select_profile(&acme), Some("acme"));
+        assert_eq!(
+            config.select_profile(&params(&[("x-tenant", "acme")])),
+            None
+        );
+        assert_eq!(
+            config.select_profile(&params(&[("profile", "other")])),
+            None
+        );
+
+        let config = EngineConfig::new(&params(&[base[0], base[1], ("profile-attrib", "on")]));
+        let config = config.unwrap();
+        assert_eq!(
+            config.select_profile(&params(&[("profile", "other")])),
+            Some("other")
+        );
+        assert_eq!(config.select_profile(&params(&[("profile", "gone")])), None);
+    }
+
+    #[test]
+    fn parses_conditions() {
+        assert_eq!(
+            parse_conditions("sip-to=*@acme.example.com, x-tenant = acme,").unwrap(),
+            vec![
+                (String::from("sip-to"), String::from("*@acme.example.com")),
+                (String::from("x-tenant"), String::from("acme")),
+            ]
+        );
+        assert!(parse_conditions("").unwrap().is_empty());
+        assert!(parse_conditions("sip-to").is_err());
+        assert!(parse_conditions("=acme").is_err());
+    }
+
+    #[test]
+    fn globs() {
+        assert!(glob("acme", "ACME"));
+        assert!(!glob("acme", "acme2"));
+        assert!(glob("*", ""));
+        assert!(glob("*@acme.example.com", "<sip:bob@acme.example.com>"));
+        assert!(!glob("*@acme.example.com", "sip:bob@example.com"));
+        assert!(glob("sip:*@*.example.com", "sip:bob@acme.example.com"));
+        assert!(glob("a*b*c", "abc"));
+        assert!(glob("a*b*c", "a-b-b-c"));
+        assert!(!glob("a*b*c", "acb"));
+        // The prefix and suffix may not overlap.
+        assert!(!glob("ab*ba", "aba"));
+    }
+}
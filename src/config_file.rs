@@ -0,0 +1,162 @@
+use crate::config::{ConfigStore, EngineConfig};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime};
+use yaml_rust2::{Yaml, YamlLoader};
+
+/// Engine param naming the TOML or YAML config file.
+pub const CONFIG_FILE_PARAM: &str = "config-file";
+/// Engine param with the milliseconds between checks of the config file.
+pub const POLL_INTERVAL_PARAM: &str = "config-poll-interval";
+const DEFAULT_POLL_INTERVAL: u64 = 5000;
+const MIN_POLL_INTERVAL: u64 = 100;
+
+/// Config of the engine `params` overridden by those of the config file.
+pub fn load(params: &HashMap<String, String>, path: &str) -> Result<EngineConfig, String> {
+    let source =
+        std::fs::read_to_string(path).map_err(|e| format!("Failed to read {:?}. {}", path, e))?;
+    merged(params, path, &source)
+}
+
+/// Milliseconds between checks of the config file.
+pub fn poll_interval(params: &HashMap<String, String>) -> Result<Duration, String> {
+    let Some(value) = params.get(POLL_INTERVAL_PARAM) else {
+        return Ok(Duration::from_millis(DEFAULT_POLL_INTERVAL));
+    };
+    value
+        .trim()
+        .parse::<u64>()
+        .ok()
+        .filter(|interval| *interval >= MIN_POLL_INTERVAL)
+        .map(Duration::from_millis)
+        .ok_or_else(|| {
+            format!(
+                "Invalid {} {:?}, expected at least {}",
+                POLL_INTERVAL_PARAM, value, MIN_POLL_INTERVAL
+            )
+        })
+}
+
+/// Reloads the config file into `store` whenever it is modified. A file
+/// that fails validation leaves the config as it is.
+pub async fn watch(
+    store: ConfigStore,
+    params: HashMap<String, String>,
+    path: String,
+    interval: Duration,
+) {
+    let mut loaded = modified(&path).await;
+    loop {
+        tokio::time::sleep(interval).await;
+        let current = modified(&path).await;
+        if current == loaded {
+            continue;
+        }
+        loaded = current;
+        let reloaded = match tokio::fs::read_to_string(&path).await {
+            Ok(source) => merged(&params, &path, &source),
+            Err(e) => Err(format!("Failed to read {:?}. {}", path, e)),
+        };
+        match reloaded {
+            Ok(config) => {
+                log::info!("Reloaded config {:?}: {:?}", path, config);
+                store.replace(config);
+            }
+            Err(e) => log::error!("Keep the config, reload of {:?} failed. {}", path, e),
+        }
+    }
+}
+
+async fn modified(path: &str) -> Option<SystemTime> {
+    let metadata = tokio::fs::metadata(path).await.ok()?;
+    metadata.modified().ok()
+}
+
+fn merged(
+    params: &HashMap<String, String>,
+    path: &str,
+    source: &str,
+) -> Result<EngineConfig, String> {
+    let mut params = params.clone();
+    params.extend(parse(path, source).map_err(|e| format!("Invalid {:?}. {}", path, e))?);
+    EngineConfig::new(&params)
+}
+
+/// Keys of a `.toml` or `.yaml` file as engine params. Nested tables
+/// like `[profile.acme]` give dotted names and arrays are joined into
+/// comma separated values.
+fn parse(path: &str, source: &str) -> Result<HashMap<String, String>, String> {
+    let extension = path.rsplit('.').next().unwrap_or_default();
+    let mut params = HashMap::new();
+    match extension.to_ascii_lowercase().as_str() {
+        "toml" => {
+            let table = source.parse::<toml::Table>().map_err(|e| e.to_string())?;
+            flatten_toml("", &table, &mut params)?;
+        }
+        "yaml" | "yml" => {
+            let documents = YamlLoader::load_from_str(source).map_err(|e| e.to_string())?;
+            match documents.first() {
+                Some(Yaml::Hash(hash)) => flatten_yaml("", hash, &mut params)?,
+                Some(_) => return Err(String::from("Expected a mapping")),
+                None => {}
+            }
+        }
+        _ => return Err(String::from("Expected a .toml, .yaml or .yml file")),
+    }
+    Ok(params)
+}
+
+fn flatten_toml(
+    prefix: &str,
+    table: &toml::Table,
+    params: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    let scalar = |value: &toml::Value| match value {
+        toml::Value::String(value) => Some(value.clone()),
+        toml::Value::Integer(value) => Some(value.to_string()),
+        toml::Value::Float(value) => Some(value.to_string()),
+        toml::Value::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    };
+    for (key, value) in table {
+        let key = format!("{}{}", prefix, key);
+        let value = match value {
+            toml::Value::Table(table) => {
+                flatten_toml(&format!("{}.", key), table, params)?;
+                continue;
+            }
+            toml::Value::Array(values) => values.iter().map(scalar).collect::<Option<Vec<_>>>(),
+            value => scalar(value).map(|value| vec![value]),
+        };
+        let value = value.ok_or_else(|| format!("{} is neither a value nor a list", key))?;
+        params.insert(key, value.join(", "));
+    }
+    Ok(())
+}
+
+fn flatten_yaml(
+    prefix: &str,
+    hash: &yaml_rust2::yaml::Hash,
+    params: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    let scalar = |value: &Yaml| match value {
+        Yaml::String(value) | Yaml::Real(value) => Some(value.clone()),
+        Yaml::Integer(value) => Some(value.to_string()),
+        Yaml::Boolean(value) => Some(value.to_string()),
+        _ => None,
+    };
+    for (key, value) in hash {
+        let key = scalar(key).ok_or_else(|| format!("Invalid key {:?}", key))?;
+        let key = format!("{}{}", prefix, key);
+        let value = match value {
+            Yaml::Hash(hash) => {
+                flatten_yaml(&format!("{}.", key), hash, params)?;
+                continue;
+            }
+            Yaml::Array(values) => values.iter().map(scalar).collect::<Option<Vec<_>>>(),
+            value => scalar(value).map(|value| vec![value]),
+        };
+        let value = value.ok_or_else(|| format!("{} is neither a value nor a list", key))?;
+        params.insert(key, value.join(", "));
+    }
+    Ok(())
+}
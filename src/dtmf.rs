@@ -0,0 +1,376 @@
+use crate::builtin::BuiltinGrammar;
+use crate::nlsml::InputMode;
+use crate::result::{Alternative, RecogResult};
+use crate::srgs::Grammar;
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+pub const DEFAULT_INTERDIGIT_TIMEOUT: usize = 5000;
+pub const DEFAULT_TERM_TIMEOUT: usize = 10000;
+pub const DEFAULT_BUFFER_TIME: usize = 0;
+
+/// Maps an RFC 4733 named event to its DTMF key.
+pub fn digit(event_id: u32) -> Option<char> {
+    let digit = match event_id {
+        0..=9 => char::from_digit(event_id, 10)?,
+        10 => '*',
+        11 => '#',
+        12 => 'A',
+        13 => 'B',
+        14 => 'C',
+        15 => 'D',
+        _ => return None,
+    };
+    Some(digit)
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct DtmfParams {
+    pub interdigit_timeout: usize,
+    pub term_timeout: usize,
+    pub term_char: Option<char>,
+    pub buffer_time: usize,
+    pub clear_buffer: bool,
+}
+
+impl Default for DtmfParams {
+    fn default() -> Self {
+        Self {
+            interdigit_timeout: DEFAULT_INTERDIGIT_TIMEOUT,
+            term_timeout: DEFAULT_TERM_TIMEOUT,
+            term_char: None,
+            buffer_time: DEFAULT_BUFFER_TIME,
+            clear_buffer: false,
+        }
+    }
+}
+
+/// DTMF grammar the collected keys are matched against.
+#[derive(Debug, Clone, PartialEq)]
+pub enum DtmfGrammar {
+    Builtin(BuiltinGrammar),
+    Srgs(Arc<Grammar>),
+}
+
+impl DtmfGrammar {
+    fn uri(&self) -> &str {
+        match self {
+            Self::Builtin(grammar) => &grammar.uri,
+            Self::Srgs(grammar) => &grammar.uri,
+        }
+    }
+
+    fn max_length(&self) -> Option<usize> {
+        match self {
+            Self::Builtin(grammar) => grammar.max_length(),
+            Self::Srgs(grammar) => Some(grammar.max_keys()),
+        }
+    }
+
+    fn interpret(&self, keys: &str) -> Option<String> {
+        match self {
+            Self::Builtin(grammar) => grammar.interpret(keys),
+            Self::Srgs(grammar) => grammar.interpret_keys(keys),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum DtmfEvent {
+    None,
+    Input,
+    Complete(RecogResult),
+}
+
+#[derive(Debug)]
+pub struct DtmfCollector {
+    params: DtmfParams,
+    grammars: Vec<DtmfGrammar>,
+    /// Grammars of the request are still loading.
+    pending: bool,
+    buffer: VecDeque<(char, usize)>,
+    digits: String,
+    active: bool,
+    since_last_digit: usize,
+    event: DtmfEvent,
+}
+
+impl Default for DtmfCollector {
+    fn default() -> Self {
+        Self {
+            params: DtmfParams::default(),
+            grammars: vec![],
+            pending: false,
+            buffer: VecDeque::new(),
+            digits: String::new(),
+            active: false,
+            since_last_digit: 0,
+            event: DtmfEvent::None,
+        }
+    }
+}
+
+impl DtmfCollector {
+    /// Arms the collector for a RECOGNIZE. Without DTMF grammars any key
+    /// completes the request with no match, unless more grammars are
+    /// `pending`. Keys buffered within DTMF-Buffer-Time are replayed into
+    /// the new request.
+    pub fn prepare(&mut self, grammars: Vec<DtmfGrammar>, params: DtmfParams, pending: bool) {
+        self.digits.clear();
+        self.since_last_digit = 0;
+        self.event = DtmfEvent::None;
+        self.active = true;
+        self.grammars = grammars;
+        self.pending = pending;
+        if params.clear_buffer {
+            self.buffer.clear();
+        }
+        let buffered = std::mem::take(&mut self.buffer);
+        self.params = params;
+        for (key, _) in buffered {
+            self.key(key);
+        }
+    }
+
+    /// Adds the grammars that were pending and matches the keys collected
+    /// meanwhile against them.
+    pub fn add_grammars(&mut self, grammars: Vec<DtmfGrammar>) {
+        self.grammars.extend(grammars);
+        self.pending = false;
+        if !self.active || self.digits.is_empty() || matches!(self.event, DtmfEvent::Complete(_)) {
+            return;
+        }
+        if let complete @ DtmfEvent::Complete(_) = self.progress() {
+            self.event = complete;
+        }
+    }
+
+    pub fn stop(&mut self) {
+        self.active = false;
+        self.digits.clear();
+        self.event = DtmfEvent::None;
+    }
+
+    pub fn event(&self) -> DtmfEvent {
+        self.event.clone()
+    }
+
+    pub fn key(&mut self, key: char) {
+        if !self.active {
+            if self.params.buffer_time > 0 {
+                self.buffer.push_back((key, 0));
+            }
+            return;
+        }
+        if matches!(self.event, DtmfEvent::Complete(_)) {
+            return;
+        }
+        self.since_last_digit = 0;
+        if Some(key) == self.params.term_char {
+            self.event = DtmfEvent::Complete(self.result());
+            return;
+        }
+        self.digits.push(key);
+        self.event = self.progress();
+    }
+
+    /// Completes once the keys are as long as the longest grammar allows.
+    fn progress(&self) -> DtmfEvent {
+        if self.pending {
+            return DtmfEvent::Input;
+        }
+        let max_length = self
+            .grammars
+            .iter()
+            .map(DtmfGrammar::max_length)
+            .try_fold(0, |max, length| length.map(|length| max.max(length)));
+        match max_length {
+            Some(max_length) if self.digits.len() >= max_length => {
+                DtmfEvent::Complete(self.result())
+            }
+            _ => DtmfEvent::Input,
+        }
+    }
+
+    /// Advances by a frame of `duration` in which `key` may have been
+    /// heard. The key is taken after the tick, so the Input it raises
+    /// lasts until the next frame.
+    pub fn frame(&mut self, key: Option<char>, duration: usize) {
+        self.tick(duration);
+        if let Some(key) = key {
+            self.key(key);
+        }
+    }
+
+    pub fn tick(&mut self, duration: usize) {
+        let buffer_time = self.params.buffer_time;
+        for (_, age) in self.buffer.iter_mut() {
+            *age += duration;
+        }
+        self.buffer.retain(|(_, age)| *age <= buffer_time);
+        if !self.active || matches!(self.event, DtmfEvent::Complete(_)) {
+            return;
+        }
+        if self.event == DtmfEvent::Input {
+            self.event = DtmfEvent::None;
+        }
+        if self.digits.is_empty() {
+            return;
+        }
+        self.since_last_digit += duration;
+        let timeout = if self.matched().is_some() {
+            self.params.term_timeout
+        } else {
+            self.params.interdigit_timeout
+        };
+        if self.since_last_digit >= timeout && !self.pending {
+            self.event = DtmfEvent::Complete(self.result());
+        }
+    }
+
+    /// Completes with whatever has been collected so far.
+    pub fn finish(&mut self) -> RecogResult {
+        let result = self.result();
+        self.event = DtmfEvent::Complete(result.clone());
+        result
+    }
+
+    pub fn digits(&self) -> &str {
+        &self.digits
+    }
+
+    fn matched(&self) -> Option<(&DtmfGrammar, String)> {
+        self.grammars.iter().find_map(|grammar| {
+            grammar
+                .interpret(&self.digits)
+                .map(|instance| (grammar, instance))
+        })
+    }
+
+    fn result(&self) -> RecogResult {
+        let alternatives = match self.matched() {
+            Some((grammar, instance)) => vec![Alternative {
+                instance: Some(instance),
+                grammar: Some(grammar.uri().to_owned()),
+                ..Alternative::new(self.digits.as_str(), 1.0)
+            }],
+            None => vec![],
+        };
+        RecogResult {
+            mode: InputMode::Dtmf,
+            alternatives,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn armed(uri: &str, params: DtmfParams) -> DtmfCollector {
+        let grammar = BuiltinGrammar::parse(uri).unwrap();
+        let mut collector = DtmfCollector::default();
+        collector.prepare(vec![DtmfGrammar::Builtin(grammar)], params, false);
+        collector
+    }
+
+    fn keys(collector: &mut DtmfCollector, keys: &str) {
+        for key in keys.chars() {
+            collector.key(key);
+        }
+    }
+
+    fn completed(collector: &DtmfCollector) -> Option<(String, Option<String>)> {
+        match collector.event() {
+            DtmfEvent::Complete(result) => Some(
+                result
+                    .best()
+                    .map(|best| (best.text.clone(), best.instance.clone()))
+                    .unwrap_or_default(),
+            ),
+            _ => None,
+        }
+    }
+
+    #[test]
+    fn maps_named_events() {
+        let keys: String = (0..16).filter_map(digit).collect();
+        assert_eq!(keys, "0123456789*#ABCD");
+        assert_eq!(digit(16), None);
+    }
+
+    #[test]
+    fn completes_at_the_longest_length() {
+        let mut collector = armed("builtin:dtmf/digits?length=3", DtmfParams::default());
+        keys(&mut collector, "12");
+        assert_eq!(collector.event(), DtmfEvent::Input);
+        collector.key('3');
+        let expected = (String::from("123"), Some(String::from("123")));
+        assert_eq!(completed(&collector), Some(expected));
+    }
+
+    #[test]
+    fn completes_on_the_term_char() {
+        let params = DtmfParams {
+            term_char: Some('#'),
+            ..DtmfParams::default()
+        };
+        let mut collector = armed("builtin:dtmf/digits", params);
+        keys(&mut collector, "42#");
+        let expected = (String::from("42"), Some(String::from("42")));
+        assert_eq!(completed(&collector), Some(expected));
+    }
+
+    #[test]
+    fn completes_on_timeouts() {
+        let params = DtmfParams {
+            interdigit_timeout: 100,
+            term_timeout: 300,
+            ..DtmfParams::default()
+        };
+        let mut collector = armed("builtin:dtmf/digits?minlength=2", params);
+        collector.key('1');
+        collector.tick(50);
+        assert_eq!(collector.event(), DtmfEvent::None);
+        collector.tick(50);
+        assert_eq!(completed(&collector), Some(Default::default()));
+
+        let params = DtmfParams {
+            interdigit_timeout: 100,
+            term_timeout: 300,
+            ..DtmfParams::default()
+        };
+        let mut collector = armed("builtin:dtmf/digits?minlength=2", params);
+        keys(&mut collector, "12");
+        collector.tick(200);
+        assert_eq!(completed(&collector), None);
+        collector.tick(100);
+        assert_eq!(completed(&collector).unwrap().0, "12");
+    }
+
+    #[test]
+    fn replays_buffered_keys() {
+        let mut collector = DtmfCollector::default();
+        collector.params.buffer_time = 100;
+        collector.key('1');
+        collector.tick(80);
+        collector.key('2');
+        collector.tick(40);
+        let params = DtmfParams {
+            buffer_time: 100,
+            ..DtmfParams::default()
+        };
+        let grammar = BuiltinGrammar::parse("builtin:dtmf/digits").unwrap();
+        collector.prepare(vec![DtmfGrammar::Builtin(grammar)], params, false);
+        assert_eq!(collector.digits(), "2");
+
+        collector.stop();
+        collector.key('3');
+        let params = DtmfParams {
+            clear_buffer: true,
+            ..collector.params.clone()
+        };
+        collector.prepare(vec![], params, false);
+        assert_eq!(collector.digits(), "");
+    }
+}
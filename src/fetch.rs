@@ -0,0 +1,297 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+
+pub const DEFAULT_FETCH_TIMEOUT: usize = 10000;
+pub const DEFAULT_MAX_BYTES: usize = 10 << 20;
+
+/// What URIs may be fetched and how much of them.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FetchPolicy {
+    /// Directories `file://` URIs may read from; none without any.
+    pub roots: Vec<PathBuf>,
+    /// Bytes a resource may have at most.
+    pub max_bytes: usize,
+    /// Hosts `http://` URIs may name, any if empty.
+    pub allow_hosts: Vec<String>,
+    /// Hosts `http://` URIs may not name.
+    pub deny_hosts: Vec<String>,
+}
+
+impl Default for FetchPolicy {
+    fn default() -> Self {
+        Self {
+            roots: vec![],
+            max_bytes: DEFAULT_MAX_BYTES,
+            allow_hosts: vec![],
+            deny_hosts: vec![],
+        }
+    }
+}
+
+impl FetchPolicy {
+    fn allows_host(&self, host: &str) -> bool {
+        let listed = |hosts: &[String]| hosts.iter().any(|item| item.eq_ignore_ascii_case(host));
+        (self.allow_hosts.is_empty() || listed(&self.allow_hosts)) && !listed(&self.deny_hosts)
+    }
+}
+
+/// Resolves a relative grammar URI against Content-Base.
+pub fn resolve(base: &str, uri: &str) -> String {
+    let uri = uri.trim();
+    let base = base.trim();
+    if base.is_empty() || uri.contains("://") || uri.contains(':') && !uri.starts_with('/') {
+        return uri.to_owned();
+    }
+    let Some(scheme_end) = base.find("://") else {
+        return uri.to_owned();
+    };
+    if uri.starts_with('/') {
+        let authority_end = base[scheme_end + 3..]
+            .find('/')
+            .map_or(base.len(), |i| scheme_end + 3 + i);
+        format!("{}{}", &base[..authority_end], uri)
+    } else {
+        let directory_end = base.rfind('/').filter(|i| *i > scheme_end + 2);
+        match directory_end {
+            Some(i) => format!("{}{}", &base[..=i], uri),
+            None => format!("{}/{}", base, uri),
+        }
+    }
+}
+
+/// Reads the resource named by a `file://` or `http://` URI within
+/// `timeout` milliseconds, as far as `policy` allows.
+pub async fn fetch(uri: &str, timeout: usize, policy: &FetchPolicy) -> Result<Vec<u8>, String> {
+    let uri = uri.split('#').next().unwrap_or_default().trim();
+    match tokio::time::timeout(Duration::from_millis(timeout as _), load(uri, policy)).await {
+        Ok(result) => result,
+        Err(_) => Err(format!("Fetch of {:?} timed out after {} ms", uri, timeout)),
+    }
+}
+
+async fn load(uri: &str, policy: &FetchPolicy) -> Result<Vec<u8>, String> {
+    if let Some(path) = uri.strip_prefix("file://") {
+        let path = path.strip_prefix("localhost").unwrap_or(path);
+        read_file(Path::new(path), policy)
+            .await
+            .map_err(|e| format!("Failed to read {:?}. {}", path, e))
+    } else if let Some(location) = uri.strip_prefix("http://") {
+        http_get(location, policy)
+            .await
+            .map_err(|e| format!("Failed to GET {:?}. {}", uri, e))
+    } else {
+        Err(format!("Unsupported URI scheme of {:?}", uri))
+    }
+}
+
+/// Reads a file inside one of the roots once links and `..` are resolved.
+async fn read_file(path: &Path, policy: &FetchPolicy) -> std::io::Result<Vec<u8>> {
+    let path = tokio::fs::canonicalize(path).await?;
+    let mut inside = false;
+    for root in &policy.roots {
+        if let Ok(root) = tokio::fs::canonicalize(root).await {
+            inside |= path.starts_with(root);
+        }
+    }
+    if !inside {
+        let reason = "outside the fetch roots";
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            reason,
+        ));
+    }
+    let file = tokio::fs::File::open(path).await?;
+    read_limited(file, policy.max_bytes).await
+}
+
+/// Reads to the end, failing once more than `max_bytes` are read.
+async fn read_limited(
+    reader: impl AsyncRead + Unpin,
+    max_bytes: usize,
+) -> std::io::Result<Vec<u8>> {
+    let mut data = vec![];
+    reader
+        .take(max_bytes as u64 + 1)
+        .read_to_end(&mut data)
+        .await?;
+    if data.len() > max_bytes {
+        let reason = format!("larger than {} bytes", max_bytes);
+        return Err(std::io::Error::new(std::io::ErrorKind::InvalidData, reason));
+    }
+    Ok(data)
+}
+
+/// Minimal HTTP/1.0 GET, so the body is never chunked.
+async fn http_get(location: &str, policy: &FetchPolicy) -> std::io::Result<Vec<u8>> {
+    let (authority, path) = match location.find('/') {
+        Some(i) => (&location[..i], &location[i..]),
+        None => (location, "/"),
+    };
+    let port = authority
+        .rsplit_once(':')
+        .filter(|(_, port)| port.parse::<u16>().is_ok());
+    let (host, address) = match port {
+        Some((host, _)) => (host, authority.to_owned()),
+        None => (authority, format!("{}:80", authority)),
+    };
+    let invalid = |reason: &str| std::io::Error::new(std::io::ErrorKind::InvalidData, reason);
+    if !policy.allows_host(host.trim_start_matches('[').trim_end_matches(']')) {
+        let reason = format!("host {:?} is not allowed", host);
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::PermissionDenied,
+            reason,
+        ));
+    }
+    let mut stream = TcpStream::connect(address).await?;
+    let request = format!(
+        "GET {} HTTP/1.0\r\nHost: {}\r\nConnection: close\r\n\r\n",
+        path, authority
+    );
+    stream.write_all(request.as_bytes()).await?;
+    // The headers come on top of the body.
+    let mut response = read_limited(stream, policy.max_bytes.saturating_add(8192)).await?;
+
+    let header_end = response
+        .windows(4)
+        .position(|window| window == b"\r\n\r\n")
+        .ok_or_else(|| invalid("no end of HTTP headers"))?;
+    let status_line = String::from_utf8_lossy(&response[..header_end]);
+    let status = status_line
+        .split_whitespace()
+        .nth(1)
+        .and_then(|status| status.parse::<u16>().ok())
+        .ok_or_else(|| invalid("no HTTP status"))?;
+    if !(200..300).contains(&status) {
+        return Err(invalid(&format!("HTTP status {}", status)));
+    }
+    response.drain(..header_end + 4);
+    if response.len() > policy.max_bytes {
+        return Err(invalid(&format!("larger than {} bytes", policy.max_bytes)));
+    }
+    Ok(response)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+
+    fn block_on<T>(future: impl std::future::Future<Output = T>) -> T {
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(future)
+    }
+
+    #[test]
+    fn resolves_relative_uris() {
+        let base = "http://host:8080/grammars/main.grxml";
+        for (uri, resolved) in [
+            ("digits.grxml", "http://host:8080/grammars/digits.grxml"),
+            ("/other/yes.grxml", "http://host:8080/other/yes.grxml"),
+            ("http://else/a.grxml", "http://else/a.grxml"),
+            ("builtin:dtmf/digits", "builtin:dtmf/digits"),
+            ("session:menu", "session:menu"),
+        ] {
+            assert_eq!(resolve(base, uri), resolved, "{}", uri);
+        }
+        assert_eq!(resolve("", "digits.grxml"), "digits.grxml");
+        assert_eq!(resolve("http://host", "a.grxml"), "http://host/a.grxml");
+        assert_eq!(
+            resolve("file:///srv/g/", "a.grxml"),
+            "file:///srv/g/a.grxml"
+        );
+    }
+
+    async fn fetch_uri(uri: &str, policy: &FetchPolicy) -> Result<Vec<u8>, String> {
+        fetch(uri, 1000, policy).await
+    }
+
+    #[test]
+    fn reads_files_only_inside_roots() {
+        let dir = std::env::temp_dir().join(format!("fetch-test-{}", std::process::id()));
+        let root = dir.join("root");
+        std::fs::create_dir_all(&root).unwrap();
+        std::fs::write(root.join("inside.txt"), b"inside").unwrap();
+        std::fs::write(root.join("large.txt"), b"too large").unwrap();
+        std::fs::write(dir.join("outside.txt"), b"outside").unwrap();
+        let policy = FetchPolicy {
+            roots: vec![root.clone()],
+            max_bytes: 6,
+            ..FetchPolicy::default()
+        };
+        let read = |path: PathBuf, policy: &FetchPolicy| {
+            block_on(fetch_uri(&format!("file://{}", path.display()), policy))
+        };
+
+        assert_eq!(read(root.join("inside.txt"), &policy).unwrap(), b"inside");
+        assert!(read(root.join("../outside.txt"), &policy).is_err());
+        assert!(read(dir.join("outside.txt"), &policy).is_err());
+        assert!(read(root.join("large.txt"), &policy)
+            .unwrap_err()
+            .contains("larger"));
+        assert!(read(root.join("inside.txt"), &FetchPolicy::default()).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Serves one response of a `body` on a local port.
+    async fn serve(body: &'static [u8]) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let address = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).await;
+            let header = format!("HTTP/1.0 200 OK\r\nContent-Length: {}\r\n\r\n", body.len());
+            let _ = stream.write_all(header.as_bytes()).await;
+            let _ = stream.write_all(body).await;
+        });
+        format!("http://{}/grammar.grxml", address)
+    }
+
+    #[test]
+    fn limits_http_body_size() {
+        let policy = FetchPolicy {
+            max_bytes: 4,
+            ..FetchPolicy::default()
+        };
+        block_on(async {
+            let uri = serve(b"fits").await;
+            assert_eq!(fetch_uri(&uri, &policy).await.unwrap(), b"fits");
+            let uri = serve(b"does not fit").await;
+            assert!(fetch_uri(&uri, &policy)
+                .await
+                .unwrap_err()
+                .contains("larger"));
+        });
+    }
+
+    #[test]
+    fn limits_http_hosts() {
+        let denied = FetchPolicy {
+            deny_hosts: vec![String::from("127.0.0.1")],
+            ..FetchPolicy::default()
+        };
+        let allowed = FetchPolicy {
+            allow_hosts: vec![String::from("grammars.example.com")],
+            ..FetchPolicy::default()
+        };
+        block_on(async {
+            for policy in [&denied, &allowed] {
+                let uri = serve(b"body").await;
+                assert!(fetch_uri(&uri, policy)
+                    .await
+                    .unwrap_err()
+                    .contains("not allowed"));
+            }
+        });
+        assert!(allowed.allows_host("Grammars.Example.com"));
+        assert!(!denied.allows_host("127.0.0.1"));
+        assert!(denied.allows_host("grammars.example.com"));
+    }
+}
@@ -0,0 +1,226 @@
+use crate::srgs;
+use std::collections::HashMap;
+
+pub const DEFAULT_THRESHOLD: f32 = 0.75;
+/// Similarity of phrases that only sound alike is scaled down by this.
+const PHONETIC_WEIGHT: f32 = 0.9;
+/// Phrases compared at most per match.
+const MAX_CANDIDATES: usize = 1000;
+
+/// Matches transcripts to grammar phrases despite misheard words.
+#[derive(Debug, Clone, PartialEq)]
+pub struct FuzzyMatcher {
+    threshold: f32,
+    synonyms: HashMap<String, String>,
+}
+
+impl FuzzyMatcher {
+    pub fn new(threshold: f32, synonyms: HashMap<String, String>) -> Self {
+        Self {
+            threshold,
+            synonyms,
+        }
+    }
+
+    /// Index and similarity of the phrase closest to `input`, if it is
+    /// similar enough. Phrases whose length alone rules them out are
+    /// skipped, and at most `MAX_CANDIDATES` are compared.
+    pub fn closest<'a>(
+        &self,
+        input: &str,
+        phrases: impl Iterator<Item = &'a str>,
+    ) -> Option<(usize, f32)> {
+        let input = Keys::new(&self.canonical(input));
+        let mut best: Option<(usize, f32)> = None;
+        let mut compared = 0;
+        for (index, phrase) in phrases.enumerate() {
+            let phrase = Keys::new(&self.canonical(phrase));
+            let floor = best.map_or(self.threshold, |(_, similarity)| {
+                similarity.max(self.threshold)
+            });
+            if input.bound(&phrase) < floor {
+                continue;
+            }
+            if compared == MAX_CANDIDATES {
+                log::warn!("Fuzzy match gave up after {} phrases", MAX_CANDIDATES);
+                break;
+            }
+            compared += 1;
+            let similarity = input.similarity(&phrase);
+            if !matches!(best, Some((_, best_similarity)) if best_similarity >= similarity) {
+                best = Some((index, similarity));
+            }
+        }
+        best.filter(|(_, similarity)| *similarity >= self.threshold)
+    }
+
+    fn canonical(&self, text: &str) -> Vec<String> {
+        srgs::normalize(text)
+            .split(' ')
+            .filter(|word| !word.is_empty())
+            .map(|word| {
+                self.synonyms
+                    .get(word)
+                    .map_or(word, String::as_str)
+                    .to_owned()
+            })
+            .collect()
+    }
+}
+
+/// Spelling and Soundex keys of a canonical word sequence.
+struct Keys {
+    spelling: Vec<char>,
+    sound: Vec<char>,
+}
+
+impl Keys {
+    fn new(words: &[String]) -> Self {
+        let sound = words.iter().map(|word| soundex(word)).collect::<Vec<_>>();
+        Self {
+            spelling: words.join(" ").chars().collect(),
+            sound: sound.join(" ").chars().collect(),
+        }
+    }
+
+    /// Similarity in `0..=1`.
+    fn similarity(&self, phrase: &Keys) -> f32 {
+        if self.spelling == phrase.spelling {
+            return 1.0;
+        }
+        let spelling = ratio(&self.spelling, &phrase.spelling);
+        let phonetic = ratio(&self.sound, &phrase.sound) * PHONETIC_WEIGHT;
+        spelling.max(phonetic)
+    }
+
+    /// Most similarity the lengths of the keys leave possible.
+    fn bound(&self, phrase: &Keys) -> f32 {
+        let bound = |a: &[char], b: &[char]| {
+            let longest = a.len().max(b.len());
+            if longest == 0 {
+                return 1.0;
+            }
+            1.0 - a.len().abs_diff(b.len()) as f32 / longest as f32
+        };
+        let spelling = bound(&self.spelling, &phrase.spelling);
+        let phonetic = bound(&self.sound, &phrase.sound) * PHONETIC_WEIGHT;
+        spelling.max(phonetic)
+    }
+}
+
+/// One minus the edit distance relative to the longer text.
+fn ratio(a: &[char], b: &[char]) -> f32 {
+    let longest = a.len().max(b.len());
+    if longest == 0 {
+        return 1.0;
+    }
+    1.0 - levenshtein(a, b) as f32 / longest as f32
+}
+
+fn levenshtein(a: &[char], b: &[char]) -> usize {
+    let mut previous = (0..=b.len()).collect::<Vec<_>>();
+    let mut current = vec![0; b.len() + 1];
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// American Soundex key of a word. Words without letters are kept as is.
+fn soundex(word: &str) -> String {
+    let code = |c: char| match c {
+        'b' | 'f' | 'p' | 'v' => '1',
+        'c' | 'g' | 'j' | 'k' | 'q' | 's' | 'x' | 'z' => '2',
+        'd' | 't' => '3',
+        'l' => '4',
+        'm' | 'n' => '5',
+        'r' => '6',
+        _ => '0',
+    };
+    let mut letters = word
+        .chars()
+        .filter(char::is_ascii_alphabetic)
+        .map(|c| c.to_ascii_lowercase());
+    let Some(first) = letters.next() else {
+        return word.to_owned();
+    };
+    let mut key = first.to_ascii_uppercase().to_string();
+    let mut last = code(first);
+    for letter in letters {
+        let digit = code(letter);
+        if digit != '0' && digit != last {
+            key.push(digit);
+            if key.len() == 4 {
+                break;
+            }
+        }
+        if letter != 'h' && letter != 'w' {
+            last = digit;
+        }
+    }
+    format!("{:0<4}", key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chars(text: &str) -> Vec<char> {
+        text.chars().collect()
+    }
+
+    #[test]
+    fn computes_soundex_keys() {
+        for (word, key) in [
+            ("robert", "R163"),
+            ("rupert", "R163"),
+            ("ashcraft", "A261"),
+            ("tymczak", "T522"),
+            ("pfister", "P236"),
+            ("lee", "L000"),
+            ("42", "42"),
+        ] {
+            assert_eq!(soundex(word), key, "{}", word);
+        }
+    }
+
+    #[test]
+    fn measures_edit_distance() {
+        assert_eq!(levenshtein(&chars("kitten"), &chars("sitting")), 3);
+        assert_eq!(levenshtein(&chars(""), &chars("abc")), 3);
+        assert_eq!(ratio(&chars("abcd"), &chars("abce")), 0.75);
+        assert_eq!(ratio(&chars(""), &chars("")), 1.0);
+    }
+
+    #[test]
+    fn finds_the_closest_phrase() {
+        let matcher = FuzzyMatcher::new(0.75, HashMap::new());
+        let phrases = ["check balance", "transfer money", "speak to an agent"];
+        assert_eq!(
+            matcher
+                .closest("check ballance", phrases.into_iter())
+                .map(|(i, _)| i),
+            Some(0)
+        );
+        assert_eq!(
+            matcher.closest("Transfer Money!", phrases.into_iter()),
+            Some((1, 1.0))
+        );
+        assert_eq!(matcher.closest("goodbye", phrases.into_iter()), None);
+    }
+
+    #[test]
+    fn applies_synonyms() {
+        let synonyms = HashMap::from([(String::from("too"), String::from("two"))]);
+        let matcher = FuzzyMatcher::new(1.0, synonyms);
+        assert_eq!(
+            matcher.closest("one too three", ["one two three"].into_iter()),
+            Some((0, 1.0))
+        );
+    }
+}
@@ -0,0 +1,166 @@
+use std::collections::HashMap;
+
+/// Language of requests without Speech-Language.
+pub const DEFAULT_LANGUAGE: &str = "en-US";
+/// Backend of the routes that are not configured.
+pub const DEFAULT_BACKEND: &str = "default";
+/// Route entry matching any language.
+const ANY_LANGUAGE: &str = "*";
+
+/// Backend or model an utterance goes to, and the language it is asked for.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Route {
+    pub language: String,
+    pub backend: String,
+}
+
+/// Backends by language tag, with fallback languages to try in turn.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LanguageRoutes {
+    default_language: String,
+    backends: HashMap<String, String>,
+    fallbacks: HashMap<String, String>,
+}
+
+impl Default for LanguageRoutes {
+    fn default() -> Self {
+        Self {
+            default_language: normalize(DEFAULT_LANGUAGE),
+            backends: HashMap::from([(ANY_LANGUAGE.to_owned(), DEFAULT_BACKEND.to_owned())]),
+            fallbacks: HashMap::new(),
+        }
+    }
+}
+
+impl LanguageRoutes {
+    /// Routes of `en-US=en-model, de=de-model, *=general` backends and
+    /// `en-GB=en-US` fallbacks. The default language must have a backend.
+    pub fn new(
+        default_language: Option<&str>,
+        backends: Option<&str>,
+        fallbacks: Option<&str>,
+    ) -> Result<Self, String> {
+        let mut routes = Self::default();
+        if let Some(language) = default_language {
+            routes.default_language = normalize(language);
+            if routes.default_language.is_empty() {
+                return Err(String::from("Empty default-language"));
+            }
+        }
+        if let Some(backends) = backends {
+            routes.backends = pairs(backends, "backend")?;
+            if routes.backends.is_empty() {
+                return Err(String::from("No backends"));
+            }
+        }
+        if let Some(fallbacks) = fallbacks {
+            routes.fallbacks = pairs(fallbacks, "language fallback")?
+                .into_iter()
+                .map(|(language, fallback)| (language, normalize(&fallback)))
+                .collect();
+        }
+        if routes.route(None).is_none() {
+            return Err(format!(
+                "No backend supports default-language {:?}",
+                routes.default_language
+            ));
+        }
+        Ok(routes)
+    }
+
+    /// Route of `language`, the default language if absent. The language
+    /// itself goes first, then its primary subtag, then its fallbacks and
+    /// finally the `*` backend. `None` if no backend supports it.
+    pub fn route(&self, language: Option<&str>) -> Option<Route> {
+        let requested = language
+            .map(normalize)
+            .filter(|language| !language.is_empty())
+            .unwrap_or_else(|| self.default_language.clone());
+        let mut language = requested.clone();
+        let mut visited = vec![];
+        loop {
+            for tag in [language.as_str(), primary(&language)] {
+                if let Some(backend) = self.backends.get(tag) {
+                    return Some(Route {
+                        language: language.clone(),
+                        backend: backend.clone(),
+                    });
+                }
+            }
+            visited.push(language.clone());
+            let fallback = self
+                .fallbacks
+                .get(&language)
+                .or_else(|| self.fallbacks.get(primary(&language)));
+            match fallback {
+                Some(fallback) if !visited.contains(fallback) => language = fallback.clone(),
+                _ => break,
+            }
+        }
+        self.backends.get(ANY_LANGUAGE).map(|backend| Route {
+            language: requested,
+            backend: backend.clone(),
+        })
+    }
+}
+
+/// Language tags compare without case and with `-` separators.
+fn normalize(language: &str) -> String {
+    language.trim().replace('_', "-").to_ascii_lowercase()
+}
+
+fn primary(language: &str) -> &str {
+    language.split('-').next().unwrap_or(language)
+}
+
+fn pairs(value: &str, what: &str) -> Result<HashMap<String, String>, String> {
+    let mut pairs = HashMap::new();
+    for pair in value.split(',').filter(|pair| !pair.trim().is_empty()) {
+        match pair.split_once('=') {
+            Some((language, target))
+                if !language.trim().is_empty() && !target.trim().is_empty() =>
+            {
+                pairs.insert(normalize(language), target.trim().to_owned());
+            }
+            _ => {
+                return Err(format!(
+                    "Invalid {} {:?}, expected language=value",
+                    what, pair
+                ))
+            }
+        }
+    }
+    Ok(pairs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rejects_invalid_routes() {
+        assert!(LanguageRoutes::new(Some(" "), None, None).is_err());
+        assert!(LanguageRoutes::new(None, Some(" , "), None).is_err());
+        assert!(LanguageRoutes::new(None, Some("en-US"), None).is_err());
+        assert!(LanguageRoutes::new(None, Some("=en-model"), None).is_err());
+        assert!(LanguageRoutes::new(None, None, Some("en-GB")).is_err());
+        assert!(LanguageRoutes::new(None, None, Some("en-GB=")).is_err());
+        assert!(LanguageRoutes::new(Some("fr-FR"), Some("de=de-model"), None).is_err());
+    }
+
+    #[test]
+    fn routes_through_fallbacks() {
+        let routes = LanguageRoutes::new(
+            Some("en-US"),
+            Some("en-US=en-model, de=de-model"),
+            Some("en-GB=en-US, fr=de-CH"),
+        )
+        .unwrap();
+        let route = |language| routes.route(language).map(|route| route.backend);
+        assert_eq!(route(None).as_deref(), Some("en-model"));
+        assert_eq!(route(Some("EN_gb")).as_deref(), Some("en-model"));
+        assert_eq!(route(Some("de-AT")).as_deref(), Some("de-model"));
+        assert_eq!(route(Some("fr-FR")).as_deref(), Some("de-model"));
+        assert_eq!(route(Some("it")), None);
+    }
+}
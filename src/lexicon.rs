@@ -0,0 +1,219 @@
+use crate::srgs;
+use roxmltree::Node;
+use std::sync::Arc;
+
+/// Alphabet of phonemes of lexicons that name none.
+const DEFAULT_ALPHABET: &str = "ipa";
+
+/// Pronunciation of a word for the backend.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pronunciation {
+    pub grapheme: String,
+    pub phoneme: String,
+    pub alphabet: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Lexeme {
+    graphemes: Vec<String>,
+    /// Normalized words of the aliases.
+    aliases: Vec<String>,
+}
+
+/// W3C PLS lexicon.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Lexicon {
+    pub uri: String,
+    lexemes: Vec<Lexeme>,
+    pronunciations: Vec<Pronunciation>,
+}
+
+impl Lexicon {
+    pub fn compile(uri: &str, source: &str) -> Result<Self, String> {
+        let document = roxmltree::Document::parse(source)
+            .map_err(|e| format!("Invalid PLS in {:?}. {}", uri, e))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "lexicon" {
+            return Err(format!("No <lexicon> in {:?}", uri));
+        }
+        let alphabet = root.attribute("alphabet").unwrap_or(DEFAULT_ALPHABET);
+        let mut lexemes = vec![];
+        let mut pronunciations = vec![];
+        for lexeme in children(root, "lexeme") {
+            let graphemes = children(lexeme, "grapheme")
+                .map(text)
+                .filter(|grapheme| !grapheme.is_empty())
+                .collect::<Vec<_>>();
+            if graphemes.is_empty() {
+                return Err(format!("Lexeme without grapheme in {:?}", uri));
+            }
+            for phoneme in children(lexeme, "phoneme") {
+                let alphabet = phoneme.attribute("alphabet").unwrap_or(alphabet);
+                pronunciations.extend(graphemes.iter().map(|grapheme| Pronunciation {
+                    grapheme: grapheme.clone(),
+                    phoneme: text(phoneme),
+                    alphabet: alphabet.to_owned(),
+                }));
+            }
+            let aliases = children(lexeme, "alias")
+                .map(|alias| srgs::normalize(&text(alias)))
+                .filter(|alias| !alias.is_empty())
+                .collect();
+            lexemes.push(Lexeme { graphemes, aliases });
+        }
+        Ok(Self {
+            uri: uri.to_owned(),
+            lexemes,
+            pronunciations,
+        })
+    }
+
+    /// Graphemes and aliases, the phrases the backend should know.
+    pub fn spellings(&self) -> impl Iterator<Item = &str> {
+        self.lexemes.iter().flat_map(|lexeme| {
+            let graphemes = lexeme.graphemes.iter();
+            graphemes.chain(lexeme.aliases.iter()).map(String::as_str)
+        })
+    }
+
+    pub fn pronunciations(&self) -> &[Pronunciation] {
+        &self.pronunciations
+    }
+
+    /// Aliases with the grapheme they stand for.
+    fn aliases(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.lexemes.iter().flat_map(|lexeme| {
+            let grapheme = lexeme.graphemes[0].as_str();
+            lexeme
+                .aliases
+                .iter()
+                .map(move |alias| (alias.as_str(), grapheme))
+        })
+    }
+}
+
+/// `text` with the aliases of `lexicons` replaced by their graphemes, or
+/// `None` if it holds no alias. The rest of the text is kept as is.
+/// Longer aliases win over their prefixes.
+pub fn substitute(lexicons: &[Arc<Lexicon>], text: &str) -> Option<String> {
+    let mut aliases = lexicons
+        .iter()
+        .flat_map(|lexicon| lexicon.aliases())
+        .map(|(alias, grapheme)| (alias.split(' ').collect::<Vec<_>>(), grapheme))
+        .collect::<Vec<_>>();
+    if aliases.is_empty() {
+        return None;
+    }
+    aliases.sort_by_key(|(alias, _)| std::cmp::Reverse(alias.len()));
+    let spans = srgs::word_spans(text);
+    let words = spans
+        .iter()
+        .map(|span| text[span.clone()].to_lowercase())
+        .collect::<Vec<_>>();
+    let mut output = String::new();
+    let mut copied = 0;
+    let mut substituted = false;
+    let mut position = 0;
+    while position < words.len() {
+        let alias = aliases.iter().find(|(alias, _)| {
+            alias.len() <= words.len() - position
+                && alias.iter().zip(&words[position..]).all(|(a, w)| a == w)
+        });
+        match alias {
+            Some((alias, grapheme)) => {
+                output.push_str(&text[copied..spans[position].start]);
+                output.push_str(grapheme);
+                position += alias.len();
+                copied = spans[position - 1].end;
+                substituted = true;
+            }
+            None => position += 1,
+        }
+    }
+    if !substituted {
+        return None;
+    }
+    output.push_str(&text[copied..]);
+    Some(output)
+}
+
+fn children<'a, 'input: 'a>(
+    node: Node<'a, 'input>,
+    name: &'a str,
+) -> impl Iterator<Item = Node<'a, 'input>> + 'a {
+    node.children()
+        .filter(move |child| child.tag_name().name() == name)
+}
+
+fn text(node: Node) -> String {
+    node.text().unwrap_or_default().trim().to_owned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PLS: &str = "<lexicon version=\"1.0\" alphabet=\"x-sampa\" \
+        xmlns=\"http://www.w3.org/2005/01/pronunciation-lexicon\">\
+        <lexeme><grapheme>ACME</grapheme><phoneme>{kmi</phoneme>\
+        <alias>ack me</alias><alias>acme corp</alias></lexeme>\
+        <lexeme><grapheme>W3C</grapheme><alias>world wide web consortium</alias></lexeme>\
+        <lexeme><grapheme>tomato</grapheme><phoneme alphabet=\"ipa\">təˈmɑːtoʊ</phoneme></lexeme>\
+        </lexicon>";
+
+    fn lexicons() -> Vec<Arc<Lexicon>> {
+        vec![Arc::new(Lexicon::compile("session:pls", PLS).unwrap())]
+    }
+
+    #[test]
+    fn compiles_lexemes() {
+        let lexicon = Lexicon::compile("session:pls", PLS).unwrap();
+        assert_eq!(
+            lexicon.spellings().collect::<Vec<_>>(),
+            [
+                "ACME",
+                "ack me",
+                "acme corp",
+                "W3C",
+                "world wide web consortium",
+                "tomato"
+            ]
+        );
+        let alphabets = lexicon
+            .pronunciations()
+            .iter()
+            .map(|pronunciation| {
+                (
+                    pronunciation.grapheme.as_str(),
+                    pronunciation.alphabet.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(alphabets, [("ACME", "x-sampa"), ("tomato", "ipa")]);
+    }
+
+    #[test]
+    fn rejects_invalid_lexicons() {
+        assert!(Lexicon::compile("session:pls", "<grammar/>").is_err());
+        assert!(Lexicon::compile("session:pls", "<lexicon><lexeme/></lexicon>").is_err());
+        assert!(Lexicon::compile("session:pls", "lexicon").is_err());
+    }
+
+    #[test]
+    fn substitutes_aliases() {
+        let lexicons = lexicons();
+        assert_eq!(
+            substitute(
+                &lexicons,
+                "Call Ack me, then the World Wide Web Consortium."
+            ),
+            Some(String::from("Call ACME, then the W3C."))
+        );
+        assert_eq!(
+            substitute(&lexicons, "acme corp"),
+            Some(String::from("ACME"))
+        );
+        assert_eq!(substitute(&lexicons, "ack"), None);
+        assert_eq!(substitute(&[], "ack me"), None);
+    }
+}
@@ -1,17 +1,71 @@
 #![allow(clippy::missing_safety_doc)]
+mod builtin;
+mod config;
+mod config_file;
+mod dtmf;
+mod fetch;
+mod fuzzy;
+mod language;
+mod lexicon;
+mod loader;
+mod message;
+mod nlsml;
 mod recognizer;
+mod result;
 mod speech_detector;
+mod srgs;
+mod tone_detector;
+mod vendor;
+mod waveform;
 
+use std::collections::HashMap;
 use std::io::Write;
 use std::mem::size_of;
+use std::sync::{Arc, Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
 
-use recognizer::RecogBuffer;
+use builtin::BuiltinGrammar;
+use config::{ConfigStore, EngineConfig, ResultFormat};
+use dtmf::DtmfEvent;
+use lexicon::Lexicon;
+use loader::{CacheControl, GrammarCache, LoadError};
+use message::RecognitionMode;
+use nlsml::InputMode;
+use recognizer::{Failure, RecogBuffer, SpeechContext};
+use result::{Alternative, RecogResult};
 use rsunimrcp_engine::RawEngine;
 use rsunimrcp_sys::uni;
 use rsunimrcp_sys::*;
 use speech_detector::SpeechDetectorEvent;
+use srgs::Grammar;
+use vendor::VendorParams;
+use waveform::{MediaType, Waveform};
 
 const RECOG_ENGINE_TASK_NAME: &[u8; 16] = b"Rust ASR-Engine\0";
+/// Frames of Input-Waveform-URI audio processed per wakeup of the engine task.
+const WAVEFORM_FRAMES_PER_POLL: usize = 50;
+/// Vendor-specific parameter of RECOGNIZE to recognize the partial utterance on STOP.
+const RECOGNIZE_ON_STOP_PARAM: &str = "recognize-on-stop";
+/// Milliseconds STOP waits for the partial utterance to be recognized.
+const RECOGNIZE_ON_STOP_TIMEOUT: u64 = 3000;
+/// Vendor-specific parameter of RECOGNIZE with the `file://` URI, relative to
+/// the waveform directory, to save the waveform to.
+const RECORD_URI_PARAM: &str = "record-uri";
+/// Vendor-specific parameter of RECOGNIZE with comma separated phrases to favour.
+const SPEECH_HINTS_PARAM: &str = "speech-hints";
+/// Vendor-specific parameter of RECOGNIZE with the boost of the phrases to favour.
+const SPEECH_HINTS_BOOST_PARAM: &str = "speech-hints-boost";
+/// Vendor-specific parameter of RECOGNIZE and INTERPRET with comma separated PLS lexicon URIs.
+const LEXICONS_PARAM: &str = "lexicons";
+/// Vendor-specific parameters handled per request rather than by `VendorParams`.
+const REQUEST_PARAMS: [&str; 6] = [
+    message::DICTATION_PARAM,
+    RECOGNIZE_ON_STOP_PARAM,
+    RECORD_URI_PARAM,
+    SPEECH_HINTS_PARAM,
+    SPEECH_HINTS_BOOST_PARAM,
+    LEXICONS_PARAM,
+];
 
 pub static ENGINE_VTABLE: uni::mrcp_engine_method_vtable_t = uni::mrcp_engine_method_vtable_t {
     destroy: Some(engine_destroy),
@@ -43,6 +97,31 @@ static STREAM_VTABLE: uni::mpf_audio_stream_vtable_t = uni::mpf_audio_stream_vta
 struct MrcpRecogEngine {
     task: *mut uni::apt_consumer_task_t,
     raw_engine: *mut RawEngine,
+    config: *mut ConfigStore,
+    config_watcher: *mut tokio::task::JoinHandle<()>,
+    grammar_cache: *mut GrammarCache,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[repr(C)]
+enum ChannelState {
+    Idle,
+    Recognizing,
+    Recognized,
+    Interpreting,
+    Stopping,
+}
+
+impl ChannelState {
+    fn accepts(&self, method_id: u32) -> bool {
+        match method_id {
+            uni::RECOGNIZER_RECOGNIZE => !matches!(self, Self::Interpreting | Self::Stopping),
+            uni::RECOGNIZER_START_INPUT_TIMERS => *self == Self::Recognizing,
+            uni::RECOGNIZER_GET_RESULT => *self == Self::Recognized,
+            uni::RECOGNIZER_INTERPRET => matches!(self, Self::Idle | Self::Recognized),
+            _ => true,
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -50,9 +129,13 @@ struct MrcpRecogEngine {
 struct MrcpRecogChannel {
     custom_engine: *mut MrcpRecogEngine,
     channel: *mut uni::mrcp_engine_channel_t,
+    state: ChannelState,
     recog_request: *mut uni::mrcp_message_t,
+    queued_request: *mut uni::mrcp_message_t,
+    interpret_request: *mut uni::mrcp_message_t,
     stop_response: *mut uni::mrcp_message_t,
     audio_buffer: *mut RecogBuffer,
+    lock: *mut Mutex<()>,
 }
 
 #[repr(C)]
@@ -60,6 +143,8 @@ enum RecogMsgType {
     OpenChannel,
     CloseChannel,
     RequestProcess,
+    /// Background work of the channel is done.
+    Poll,
 }
 
 #[repr(C)]
@@ -84,6 +169,9 @@ pub unsafe extern "C" fn mrcp_plugin_create(pool: *mut uni::apr_pool_t) -> *mut
 
     let custom_engine = uni::apr_palloc(pool, size_of::<MrcpRecogEngine>()) as *mut MrcpRecogEngine;
     (*custom_engine).raw_engine = std::ptr::null_mut() as _;
+    (*custom_engine).config = std::ptr::null_mut() as _;
+    (*custom_engine).config_watcher = std::ptr::null_mut() as _;
+    (*custom_engine).grammar_cache = std::ptr::null_mut() as _;
     let msg_pool = uni::apt_task_msg_pool_create_dynamic(size_of::<RecogMsg>(), pool);
     (*custom_engine).task = uni::apt_consumer_task_create(custom_engine as _, msg_pool, pool);
     if (*custom_engine).task.is_null() {
@@ -118,7 +206,16 @@ unsafe extern "C" fn engine_destroy(engine: *mut uni::mrcp_engine_t) -> uni::apt
         (*custom_engine).task = std::ptr::null_mut() as _;
         log::trace!("Task {:?} destroyed = {:?}", task, destroyed);
     }
+    if !(*custom_engine).config_watcher.is_null() {
+        let watcher = Box::from_raw((*custom_engine).config_watcher);
+        watcher.abort();
+        (*custom_engine).config_watcher = std::ptr::null_mut() as _;
+    }
     RawEngine::destroy((*custom_engine).raw_engine);
+    ConfigStore::destroy((*custom_engine).config);
+    (*custom_engine).config = std::ptr::null_mut() as _;
+    GrammarCache::destroy((*custom_engine).grammar_cache);
+    (*custom_engine).grammar_cache = std::ptr::null_mut() as _;
     uni::TRUE
 }
 
@@ -129,16 +226,71 @@ unsafe extern "C" fn engine_open(engine: *mut uni::mrcp_engine_t) -> uni::apt_bo
         engine,
         custom_engine
     );
+    let params = engine_params(engine);
+    let (config, config_file) = match engine_config(&params) {
+        Ok(config) => config,
+        Err(e) => {
+            log::error!("Invalid config of Engine {:?}. {}", engine, e);
+            return inline_mrcp_engine_open_respond(engine, uni::FALSE);
+        }
+    };
     if !(*custom_engine).task.is_null() {
         let task = uni::apt_consumer_task_base_get((*custom_engine).task);
         let started = uni::apt_task_start(task);
         log::debug!("Task = {:?} started = {:?}.", task, started);
     }
     (*custom_engine).raw_engine = RawEngine::leaked(engine);
-    log::info!("Opened with raw Engine: {:?}", (*custom_engine).raw_engine);
+    (*custom_engine).config = ConfigStore::leaked(config);
+    if let Some((path, interval)) = config_file {
+        let store = (*(*custom_engine).config).clone();
+        let watch = config_file::watch(store, params, path, interval);
+        let watcher = (*(*custom_engine).raw_engine)
+            .engine()
+            .async_handle()
+            .spawn(watch);
+        (*custom_engine).config_watcher = Box::into_raw(Box::new(watcher));
+    }
+    (*custom_engine).grammar_cache = GrammarCache::leaked();
+    log::info!(
+        "Opened with raw Engine: {:?}, config: {:?}",
+        (*custom_engine).raw_engine,
+        *(*custom_engine).config
+    );
     inline_mrcp_engine_open_respond(engine, uni::TRUE)
 }
 
+/// Config of the engine params overridden by the config file they name,
+/// with the file and its poll interval if there is one.
+fn engine_config(
+    params: &HashMap<String, String>,
+) -> Result<(EngineConfig, Option<(String, Duration)>), String> {
+    let Some(path) = params.get(config_file::CONFIG_FILE_PARAM) else {
+        return Ok((EngineConfig::new(params)?, None));
+    };
+    let interval = config_file::poll_interval(params)?;
+    let config = config_file::load(params, path)?;
+    Ok((config, Some((path.clone(), interval))))
+}
+
+unsafe fn engine_params(engine: *mut uni::mrcp_engine_t) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    if (*engine).config.is_null() || (*(*engine).config).params.is_null() {
+        return params;
+    }
+    let header = uni::apr_table_elts((*(*engine).config).params);
+    let entry = (*header).elts as *mut uni::apr_table_entry_t;
+    for i in 0..(*header).nelts {
+        let entry = entry.offset(i as _);
+        let key = std::ffi::CStr::from_ptr((*entry).key);
+        let val = std::ffi::CStr::from_ptr((*entry).val);
+        params.insert(
+            key.to_string_lossy().into_owned(),
+            val.to_string_lossy().into_owned(),
+        );
+    }
+    params
+}
+
 unsafe extern "C" fn engine_close(engine: *mut uni::mrcp_engine_t) -> uni::apt_bool_t {
     let custom_engine = (*engine).obj as *mut MrcpRecogEngine;
     log::info!(
@@ -165,9 +317,17 @@ unsafe extern "C" fn engine_create_channel(
     let custom_channel =
         uni::apr_palloc(pool, size_of::<MrcpRecogChannel>()) as *mut MrcpRecogChannel;
     (*custom_channel).custom_engine = (*engine).obj as _;
+    (*custom_channel).state = ChannelState::Idle;
     (*custom_channel).recog_request = std::ptr::null_mut() as _;
+    (*custom_channel).queued_request = std::ptr::null_mut() as _;
+    (*custom_channel).interpret_request = std::ptr::null_mut() as _;
     (*custom_channel).stop_response = std::ptr::null_mut() as _;
-    (*custom_channel).audio_buffer = RecogBuffer::leaked(rs_engine);
+    (*custom_channel).lock = Box::into_raw(Box::new(Mutex::new(())));
+    (*custom_channel).audio_buffer = RecogBuffer::leaked(
+        rs_engine,
+        (*(*custom_engine).config).clone(),
+        (*(*custom_engine).grammar_cache).clone(),
+    );
 
     let capabilities = inline_mpf_sink_stream_capabilities_create(pool);
     inline_mpf_codec_capabilities_add(
@@ -203,11 +363,13 @@ pub unsafe extern "C" fn channel_destroy(
     log::debug!("Channel {:?} destroy.", channel);
     let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
     RecogBuffer::destroy((*custom_channel).audio_buffer);
+    drop(Box::from_raw((*custom_channel).lock));
     uni::TRUE
 }
 
 pub unsafe extern "C" fn channel_open(channel: *mut uni::mrcp_engine_channel_t) -> uni::apt_bool_t {
     log::debug!("Channel {:?} open.", channel);
+    let mut attribs = HashMap::new();
     if !(*channel).attribs.is_null() {
         let header = uni::apr_table_elts((*channel).attribs);
         let entry = (*header).elts as *mut uni::apr_table_entry_t;
@@ -216,8 +378,21 @@ pub unsafe extern "C" fn channel_open(channel: *mut uni::mrcp_engine_channel_t)
             let key = std::ffi::CStr::from_ptr((*entry).key);
             let val = std::ffi::CStr::from_ptr((*entry).val);
             log::info!("Attrib name {:?} value {:?}", key, val);
+            attribs.insert(
+                key.to_string_lossy().into_owned(),
+                val.to_string_lossy().into_owned(),
+            );
         }
     }
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    {
+        let _guard = rs_recog_channel_lock(custom_channel);
+        (*(*custom_channel).audio_buffer).select_profile(&attribs);
+        let wakeup = WakeupChannel(channel);
+        (*(*custom_channel).audio_buffer)
+            .wakeup()
+            .set(move || wakeup.signal());
+    }
     rs_recog_msg_signal(
         RecogMsgType::OpenChannel,
         channel,
@@ -225,8 +400,24 @@ pub unsafe extern "C" fn channel_open(channel: *mut uni::mrcp_engine_channel_t)
     )
 }
 
+/// Channel the background tasks wake the engine task for.
+struct WakeupChannel(*mut uni::mrcp_engine_channel_t);
+
+// The pointer is only signalled while the channel is open.
+unsafe impl Send for WakeupChannel {}
+
+impl WakeupChannel {
+    fn signal(&self) {
+        unsafe {
+            rs_recog_msg_signal(RecogMsgType::Poll, self.0, std::ptr::null_mut() as _);
+        }
+    }
+}
+
 unsafe extern "C" fn channel_close(channel: *mut uni::mrcp_engine_channel_t) -> uni::apt_bool_t {
     log::info!("Channel {:?} close.", channel);
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    (*(*custom_channel).audio_buffer).wakeup().clear();
     rs_recog_msg_signal(
         RecogMsgType::CloseChannel,
         channel,
@@ -259,19 +450,171 @@ unsafe fn rs_recog_channel_recognize(
         (*response).start_line.status_code = uni::MRCP_STATUS_CODE_METHOD_FAILED;
         return uni::FALSE;
     }
+    if (*custom_channel).state == ChannelState::Recognizing {
+        if message::cancel_if_queue((*custom_channel).recog_request) {
+            log::info!("Cancel RECOGNIZE in progress for {:?}", channel);
+            rs_recog_recognition_complete(
+                custom_channel,
+                uni::RECOGNIZER_COMPLETION_CAUSE_CANCELLED,
+                &RecogResult::default(),
+            );
+        } else if (*custom_channel).queued_request.is_null() {
+            log::info!("Queue RECOGNIZE {:?} for {:?}", request, channel);
+            (*custom_channel).queued_request = request;
+            (*response).start_line.request_state = uni::MRCP_REQUEST_STATE_PENDING;
+            return inline_mrcp_engine_channel_message_send(channel, response);
+        } else {
+            log::warn!("RECOGNIZE queue is full for {:?}", channel);
+            (*response).start_line.status_code = uni::MRCP_STATUS_CODE_METHOD_NOT_VALID;
+            return uni::FALSE;
+        }
+    }
+    rs_recog_recognition_start(custom_channel, request);
+
+    (*response).start_line.request_state = uni::MRCP_REQUEST_STATE_INPROGRESS;
+    inline_mrcp_engine_channel_message_send(channel, response)
+}
+
+unsafe fn rs_recog_recognition_start(
+    recog_channel: *mut MrcpRecogChannel,
+    request: *mut uni::mrcp_message_t,
+) {
     let recog_header = rsunimrcp_sys::headers::RecogHeaders::new(request);
     log::info!(
         "Channel {:?}\nRecognize-headers: {:?}",
-        channel,
+        (*recog_channel).channel,
         recog_header
     );
-    (*(*custom_channel).audio_buffer).prepare(recog_header);
+    let uris = message::grammar_uris(request);
+    let builtins = uris
+        .iter()
+        .filter_map(|uri| BuiltinGrammar::parse(uri))
+        .collect::<Vec<_>>();
+    let srgs = builtins.len() < uris.len();
+    (*(*recog_channel).audio_buffer).prepare(
+        recog_header,
+        builtins,
+        srgs,
+        message::dtmf_params(request),
+    );
+    let params = VendorParams::parse(message::vendor_params(request), &REQUEST_PARAMS);
+    (*(*recog_channel).audio_buffer).tune(params);
+    (*(*recog_channel).audio_buffer).route(message::speech_language(request));
+    (*(*recog_channel).audio_buffer).hint(rs_recog_speech_hints(request));
+    (*(*recog_channel).audio_buffer).record(message::save_waveform(request));
+    if let Some(uri) = message::input_waveform_uri(request) {
+        log::info!(
+            "Recognize from Input-Waveform-URI {:?} in {:?}",
+            uri,
+            (*recog_channel).channel
+        );
+        let timeout = rs_recog_fetch_timeout(recog_channel, request);
+        (*(*recog_channel).audio_buffer).input_waveform(uri, timeout);
+    }
+    rs_recog_grammars_load(recog_channel, request);
+    (*recog_channel).recog_request = request;
+    (*recog_channel).state = ChannelState::Recognizing;
+}
 
-    (*response).start_line.request_state = uni::MRCP_REQUEST_STATE_INPROGRESS;
-    inline_mrcp_engine_channel_message_send(channel, response);
+/// Phrases to favour given in the Vendor-Specific-Parameters of a RECOGNIZE.
+unsafe fn rs_recog_speech_hints(request: *mut uni::mrcp_message_t) -> SpeechContext {
+    let params = message::vendor_params(request);
+    let phrases = params
+        .get(SPEECH_HINTS_PARAM)
+        .map(|hints| {
+            hints
+                .split(',')
+                .map(srgs::normalize)
+                .filter(|phrase| !phrase.is_empty())
+                .collect()
+        })
+        .unwrap_or_default();
+    let boost = params.get(SPEECH_HINTS_BOOST_PARAM).and_then(|boost| {
+        let parsed = boost.trim().parse::<f32>().ok();
+        if parsed.is_none() {
+            log::warn!("Invalid {} {:?}", SPEECH_HINTS_BOOST_PARAM, boost);
+        }
+        parsed
+    });
+    SpeechContext {
+        phrases,
+        boost,
+        ..Default::default()
+    }
+}
 
-    (*custom_channel).recog_request = request;
-    uni::TRUE
+/// Lexicon URIs of the vendor-specific parameter, resolved against Content-Base.
+unsafe fn rs_recog_lexicon_uris(request: *mut uni::mrcp_message_t) -> Vec<String> {
+    let Some(lexicons) = message::vendor_params(request).remove(LEXICONS_PARAM) else {
+        return vec![];
+    };
+    let base = message::content_base(request);
+    lexicons
+        .split(',')
+        .filter(|uri| !uri.trim().is_empty())
+        .map(|uri| fetch::resolve(&base, uri))
+        .collect()
+}
+
+/// Defines the inline grammar of a RECOGNIZE and starts loading the
+/// grammars it references.
+unsafe fn rs_recog_grammars_load(
+    recog_channel: *mut MrcpRecogChannel,
+    request: *mut uni::mrcp_message_t,
+) {
+    if let Err(e) = rs_recog_inline_grammar(recog_channel, request) {
+        (*(*recog_channel).audio_buffer).grammars_failed(LoadError::Compile(e));
+        return;
+    }
+    let base = message::content_base(request);
+    let uris = message::grammar_uris(request)
+        .iter()
+        .filter(|uri| BuiltinGrammar::parse(uri).is_none())
+        .map(|uri| fetch::resolve(&base, uri))
+        .collect();
+    let timeout = rs_recog_fetch_timeout(recog_channel, request);
+    let lexicons = rs_recog_lexicon_uris(request);
+    let control = rs_recog_cache_control(request);
+    (*(*recog_channel).audio_buffer).load_grammars(uris, lexicons, timeout, control);
+}
+
+/// Compiles the grammar in the body of a RECOGNIZE or INTERPRET, if any,
+/// and keeps it for the session like DEFINE-GRAMMAR does.
+unsafe fn rs_recog_inline_grammar(
+    recog_channel: *mut MrcpRecogChannel,
+    request: *mut uni::mrcp_message_t,
+) -> Result<(), String> {
+    if message::is_uri_list(&message::content_type(request)) {
+        return Ok(());
+    }
+    let body = message::body(request);
+    let uris = message::grammar_uris(request);
+    let Some(uri) = uris.first().filter(|_| !body.trim().is_empty()) else {
+        return Ok(());
+    };
+    let grammar = Grammar::compile(uri, &body).inspect_err(|e| {
+        log::error!("Inline grammar of {:?}: {}", request, e);
+    })?;
+    (*(*recog_channel).audio_buffer).define_grammar(grammar);
+    Ok(())
+}
+
+unsafe fn rs_recog_fetch_timeout(
+    recog_channel: *mut MrcpRecogChannel,
+    request: *mut uni::mrcp_message_t,
+) -> usize {
+    message::fetch_timeout(request).unwrap_or(
+        (*(*recog_channel).audio_buffer)
+            .config()
+            .limits
+            .fetch_timeout,
+    )
+}
+
+unsafe fn rs_recog_cache_control(request: *mut uni::mrcp_message_t) -> CacheControl {
+    message::cache_control(request)
+        .map(|value| CacheControl::parse(&value))
+        .unwrap_or_default()
 }
 
 unsafe fn rs_recog_channel_stop(
@@ -281,8 +624,78 @@ unsafe fn rs_recog_channel_stop(
 ) -> uni::apt_bool_t {
     log::info!("Process Stop request {:?} for {:?}", request, channel);
     let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
-    (*custom_channel).stop_response = response;
-    uni::TRUE
+    if (*custom_channel).state == ChannelState::Stopping {
+        log::info!("STOP is already in progress for {:?}", channel);
+        return inline_mrcp_engine_channel_message_send(channel, response);
+    }
+    let recog_request = (*custom_channel).recog_request;
+    if !recog_request.is_null()
+        && rs_recog_recognize_on_stop(recog_request)
+        && (*(*custom_channel).audio_buffer).recognize_partial(RECOGNIZE_ON_STOP_TIMEOUT)
+    {
+        log::info!("Recognize partial utterance on STOP for {:?}", channel);
+        (*custom_channel).state = ChannelState::Stopping;
+        (*custom_channel).stop_response = response;
+        return uni::TRUE;
+    }
+    rs_recog_stop_respond(custom_channel, response)
+}
+
+/// Whether the RECOGNIZE asks for the utterance cut by STOP to be recognized.
+unsafe fn rs_recog_recognize_on_stop(request: *mut uni::mrcp_message_t) -> bool {
+    let params = message::vendor_params(request);
+    let Some(value) = params.get(RECOGNIZE_ON_STOP_PARAM) else {
+        return false;
+    };
+    config::parse_bool(value).unwrap_or_else(|| {
+        log::warn!("Invalid {} {:?}", RECOGNIZE_ON_STOP_PARAM, value);
+        false
+    })
+}
+
+/// Sends the STOP response listing the terminated requests.
+unsafe fn rs_recog_stop_respond(
+    recog_channel: *mut MrcpRecogChannel,
+    response: *mut uni::mrcp_message_t,
+) -> uni::apt_bool_t {
+    let stopped = [
+        (*recog_channel).recog_request,
+        (*recog_channel).queued_request,
+        (*recog_channel).interpret_request,
+    ]
+    .into_iter()
+    .filter(|request| !request.is_null())
+    .collect::<Vec<_>>();
+    if !stopped.is_empty() {
+        let generic_header = inline_mrcp_generic_header_prepare(response);
+        if !generic_header.is_null() {
+            let list = &mut (*generic_header).active_request_id_list;
+            for (index, request) in stopped.iter().enumerate() {
+                list.ids[index] = (**request).start_line.request_id;
+            }
+            list.count = stopped.len() as _;
+            uni::mrcp_generic_header_property_add(
+                response,
+                uni::GENERIC_HEADER_ACTIVE_REQUEST_ID_LIST as _,
+            );
+        }
+    }
+    (*recog_channel).recog_request = std::ptr::null_mut() as _;
+    (*recog_channel).queued_request = std::ptr::null_mut() as _;
+    (*recog_channel).interpret_request = std::ptr::null_mut() as _;
+    (*(*recog_channel).audio_buffer).complete();
+    (*recog_channel).state = rs_recog_settled_state(recog_channel);
+    inline_mrcp_engine_channel_message_send((*recog_channel).channel, response)
+}
+
+/// State of a channel with no request in progress. GET-RESULT stays valid
+/// while the last result is kept.
+unsafe fn rs_recog_settled_state(recog_channel: *mut MrcpRecogChannel) -> ChannelState {
+    if (*(*recog_channel).audio_buffer).last_result().is_some() {
+        ChannelState::Recognized
+    } else {
+        ChannelState::Idle
+    }
 }
 
 unsafe fn rs_recog_channel_timers_start(
@@ -296,21 +709,231 @@ unsafe fn rs_recog_channel_timers_start(
     inline_mrcp_engine_channel_message_send(channel, response)
 }
 
+unsafe fn rs_recog_channel_set_params(
+    channel: *mut uni::mrcp_engine_channel_t,
+    request: *mut uni::mrcp_message_t,
+    response: *mut uni::mrcp_message_t,
+) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    let params = VendorParams::parse(message::vendor_params(request), &REQUEST_PARAMS);
+    log::info!("Set parameters {:?} of {:?}", params, channel);
+    (*(*custom_channel).audio_buffer).set_params(params);
+    inline_mrcp_engine_channel_message_send(channel, response)
+}
+
+unsafe fn rs_recog_channel_get_result(
+    channel: *mut uni::mrcp_engine_channel_t,
+    request: *mut uni::mrcp_message_t,
+    response: *mut uni::mrcp_message_t,
+) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    let Some(result) = (*(*custom_channel).audio_buffer).last_result() else {
+        log::warn!("No recognition result to GET in {:?}", channel);
+        (*response).start_line.status_code = uni::MRCP_STATUS_CODE_METHOD_NOT_VALID;
+        return uni::FALSE;
+    };
+    let result = result.filtered(
+        message::confidence_threshold(request).unwrap_or(result::DEFAULT_CONFIDENCE_THRESHOLD),
+        message::n_best_list_length(request).unwrap_or(result::DEFAULT_N_BEST_LIST_LENGTH),
+    );
+    log::info!("Send GET-RESULT {:?} for {:?}", result, channel);
+    rs_recog_result_load(custom_channel, &result, response);
+    inline_mrcp_engine_channel_message_send(channel, response)
+}
+
+unsafe fn rs_recog_channel_interpret(
+    channel: *mut uni::mrcp_engine_channel_t,
+    request: *mut uni::mrcp_message_t,
+    response: *mut uni::mrcp_message_t,
+) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    if message::interpret_text(request).is_none() {
+        log::warn!("INTERPRET without Interpret-Text in {:?}", channel);
+        (*response).start_line.status_code = uni::MRCP_STATUS_CODE_MISSING_PARAM;
+        return uni::FALSE;
+    }
+    match rs_recog_inline_grammar(custom_channel, request) {
+        Ok(()) => {
+            let base = message::content_base(request);
+            let uris = message::grammar_uris(request)
+                .iter()
+                .filter(|uri| BuiltinGrammar::parse(uri).is_none())
+                .map(|uri| fetch::resolve(&base, uri))
+                .collect();
+            let timeout = rs_recog_fetch_timeout(custom_channel, request);
+            let lexicons = rs_recog_lexicon_uris(request);
+            let control = rs_recog_cache_control(request);
+            (*(*custom_channel).audio_buffer).load_interpretation(uris, lexicons, timeout, control);
+        }
+        Err(e) => (*(*custom_channel).audio_buffer).interpretation_failed(LoadError::Compile(e)),
+    }
+    (*custom_channel).interpret_request = request;
+    (*custom_channel).state = ChannelState::Interpreting;
+
+    (*response).start_line.request_state = uni::MRCP_REQUEST_STATE_INPROGRESS;
+    inline_mrcp_engine_channel_message_send(channel, response)
+}
+
+/// Completes the INTERPRET in progress once its grammars are loaded.
+unsafe fn rs_recog_interpretation_complete(
+    recog_channel: *mut MrcpRecogChannel,
+) -> uni::apt_bool_t {
+    let Some(loaded) = (*(*recog_channel).audio_buffer).interpretation() else {
+        return uni::FALSE;
+    };
+    let request = (*recog_channel).interpret_request;
+    let builtin = message::grammar_uris(request)
+        .iter()
+        .any(|uri| BuiltinGrammar::parse(uri).is_some());
+    let (cause, result) = match loaded {
+        Ok(loaded) if loaded.grammars.is_empty() && !builtin => {
+            log::info!("No grammar to INTERPRET with in {:?}", recog_channel);
+            (
+                uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH,
+                RecogResult::default(),
+            )
+        }
+        Ok(loaded) => {
+            let text = message::interpret_text(request).unwrap_or_default();
+            let result = RecogResult {
+                mode: InputMode::Text,
+                alternatives: vec![Alternative::new(text, 1.0)],
+            };
+            let (grammars, lexicons) = (&loaded.grammars, &loaded.lexicons);
+            let result =
+                rs_recog_grammar_interpret(recog_channel, request, grammars, lexicons, result)
+                    .attributed(message::grammar_uri(request))
+                    .filtered(
+                        message::confidence_threshold(request)
+                            .unwrap_or(result::DEFAULT_CONFIDENCE_THRESHOLD),
+                        message::n_best_list_length(request)
+                            .unwrap_or(result::DEFAULT_N_BEST_LIST_LENGTH),
+                    );
+            if result.alternatives.is_empty() {
+                (uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH, result)
+            } else {
+                (uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS, result)
+            }
+        }
+        Err(e) => {
+            log::error!("Failed to load grammars of INTERPRET. {:?}", e);
+            let cause = match e {
+                LoadError::Fetch(_) => uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_LOAD_FAILURE,
+                LoadError::Compile(_) => uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_COMP_FAILURE,
+            };
+            (cause, RecogResult::default())
+        }
+    };
+    (*recog_channel).interpret_request = std::ptr::null_mut() as _;
+    (*recog_channel).state = rs_recog_settled_state(recog_channel);
+    let message = uni::mrcp_event_create(
+        request,
+        uni::RECOGNIZER_INTERPRETATION_COMPLETE as _,
+        (*request).pool,
+    );
+    if message.is_null() {
+        log::error!("Unable to create event INTERPRETATION COMPLETE");
+        return uni::FALSE;
+    }
+    rs_recog_completion_cause_set(message, cause);
+    (*message).start_line.request_state = uni::MRCP_REQUEST_STATE_COMPLETE;
+    if cause == uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS {
+        rs_recog_result_load(recog_channel, &result, message);
+    }
+    log::info!(
+        "Send INTERPRETATION COMPLETE {:?} for {:?}",
+        result,
+        (*recog_channel).channel
+    );
+    inline_mrcp_engine_channel_message_send((*recog_channel).channel, message)
+}
+
+unsafe fn rs_recog_channel_define_grammar(
+    channel: *mut uni::mrcp_engine_channel_t,
+    request: *mut uni::mrcp_message_t,
+    response: *mut uni::mrcp_message_t,
+) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    let cause = if message::is_uri_list(&message::content_type(request)) {
+        log::warn!("DEFINE-GRAMMAR by URI is not supported in {:?}", channel);
+        (*response).start_line.status_code = uni::MRCP_STATUS_CODE_UNSUPPORTED_PARAM_VALUE;
+        uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_LOAD_FAILURE
+    } else {
+        let Some(uri) = message::grammar_uri(request) else {
+            log::warn!("DEFINE-GRAMMAR without Content-Id in {:?}", channel);
+            (*response).start_line.status_code = uni::MRCP_STATUS_CODE_MISSING_PARAM;
+            return uni::FALSE;
+        };
+        match Grammar::compile(&uri, &message::body(request)) {
+            Ok(grammar) => {
+                (*(*custom_channel).audio_buffer).define_grammar(grammar);
+                uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS
+            }
+            Err(e) => {
+                log::error!("DEFINE-GRAMMAR in {:?}: {}", channel, e);
+                uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_COMP_FAILURE
+            }
+        }
+    };
+    rs_recog_completion_cause_set(response, cause);
+    inline_mrcp_engine_channel_message_send(channel, response)
+}
+
+/// Serializes the media thread and the engine task over the channel state.
+unsafe fn rs_recog_channel_lock<'a>(recog_channel: *mut MrcpRecogChannel) -> MutexGuard<'a, ()> {
+    (*(*recog_channel).lock)
+        .lock()
+        .unwrap_or_else(PoisonError::into_inner)
+}
+
 unsafe fn rs_recog_channel_request_dispatch(
     channel: *mut uni::mrcp_engine_channel_t,
     request: *mut uni::mrcp_message_t,
 ) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    let _guard = rs_recog_channel_lock(custom_channel);
     let mut processed = uni::FALSE;
     let response = uni::mrcp_response_create(request, (*request).pool);
     let method_id = (*request).start_line.method_id;
+    if !(*custom_channel).state.accepts(method_id as _) {
+        log::warn!(
+            "Method {:?} is not valid in state {:?} of {:?}",
+            method_id,
+            (*custom_channel).state,
+            channel
+        );
+        (*response).start_line.status_code = uni::MRCP_STATUS_CODE_METHOD_NOT_VALID;
+        inline_mrcp_engine_channel_message_send(channel, response);
+        return uni::TRUE;
+    }
+    // RECOGNIZE takes its config once it starts, and the one in progress
+    // keeps it.
+    if matches!(
+        method_id as u32,
+        uni::RECOGNIZER_SET_PARAMS | uni::RECOGNIZER_DEFINE_GRAMMAR | uni::RECOGNIZER_INTERPRET
+    ) && !matches!(
+        (*custom_channel).state,
+        ChannelState::Recognizing | ChannelState::Stopping
+    ) {
+        (*(*custom_channel).audio_buffer).refresh_config();
+    }
     match method_id as u32 {
-        uni::RECOGNIZER_SET_PARAMS => {}
+        uni::RECOGNIZER_SET_PARAMS => {
+            processed = rs_recog_channel_set_params(channel, request, response);
+        }
         uni::RECOGNIZER_GET_PARAMS => {}
-        uni::RECOGNIZER_DEFINE_GRAMMAR => {}
+        uni::RECOGNIZER_DEFINE_GRAMMAR => {
+            processed = rs_recog_channel_define_grammar(channel, request, response);
+        }
         uni::RECOGNIZER_RECOGNIZE => {
             processed = rs_recog_channel_recognize(channel, request, response);
         }
-        uni::RECOGNIZER_GET_RESULT => {}
+        uni::RECOGNIZER_INTERPRET => {
+            processed = rs_recog_channel_interpret(channel, request, response);
+        }
+        uni::RECOGNIZER_GET_RESULT => {
+            processed = rs_recog_channel_get_result(channel, request, response);
+        }
         uni::RECOGNIZER_START_INPUT_TIMERS => {
             processed = rs_recog_channel_timers_start(channel, request, response);
         }
@@ -362,19 +985,46 @@ unsafe fn rs_recog_start_of_input(recog_channel: *mut MrcpRecogChannel) -> uni::
 }
 
 unsafe fn rs_recog_result_load(
-    recognized: &str,
+    recog_channel: *mut MrcpRecogChannel,
+    recognized: &RecogResult,
     message: *mut uni::mrcp_message_t,
 ) -> uni::apt_bool_t {
+    let (content_type, result) = match (*(*recog_channel).audio_buffer).config().result_format {
+        ResultFormat::Nlsml => {
+            let interpretations = recognized
+                .alternatives
+                .iter()
+                .map(|alternative| nlsml::Interpretation {
+                    grammar: alternative.grammar.as_deref(),
+                    confidence: alternative.confidence,
+                    mode: recognized.mode,
+                    input: &alternative.text,
+                    instance: alternative.instance.as_deref().unwrap_or(&alternative.text),
+                })
+                .collect::<Vec<_>>();
+            (
+                format!("{}; charset=UTF-8\0", nlsml::CONTENT_TYPE),
+                nlsml::result(&interpretations),
+            )
+        }
+        ResultFormat::Text => (
+            String::from("text/plain; charset=UTF-8\0"),
+            recognized
+                .best()
+                .map(|alternative| alternative.text.clone())
+                .unwrap_or_default(),
+        ),
+    };
     let generic_header = inline_mrcp_generic_header_prepare(message);
     if !generic_header.is_null() {
         inline_apt_string_assign(
             &mut (*generic_header).content_type as _,
-            b"text/plain; charset=UTF-8\0".as_ptr() as _,
+            content_type.as_ptr() as _,
             (*message).pool,
         );
         uni::mrcp_generic_header_property_add(message, uni::GENERIC_HEADER_CONTENT_TYPE as _);
     }
-    let result = recognized.as_bytes();
+    let result = result.as_bytes();
     inline_apt_string_assign_n(
         &mut (*message).body as _,
         result.as_ptr() as _,
@@ -385,13 +1035,100 @@ unsafe fn rs_recog_result_load(
     uni::TRUE
 }
 
+/// Annotates every alternative with the value of the speech grammar that
+/// accepts it best, the heavier grammar on ties, and drops the others.
+/// Fuzzy matches scale the confidence by their similarity, and the grammar
+/// weight ranks the alternatives along with it. Without grammars the result is left as it is,
+/// apart from lexicon aliases normalized to their graphemes.
+unsafe fn rs_recog_grammar_interpret(
+    recog_channel: *mut MrcpRecogChannel,
+    request: *mut uni::mrcp_message_t,
+    grammars: &[Arc<Grammar>],
+    lexicons: &[Arc<Lexicon>],
+    mut result: RecogResult,
+) -> RecogResult {
+    for alternative in result.alternatives.iter_mut() {
+        if let Some(text) = lexicon::substitute(lexicons, &alternative.text) {
+            log::info!("Normalized {:?} to {:?}", alternative.text, text);
+            alternative.text = text;
+        }
+    }
+    let refs = message::grammar_refs(request);
+    let builtins = refs
+        .iter()
+        .filter_map(|grammar| BuiltinGrammar::parse(&grammar.uri))
+        .collect::<Vec<_>>();
+    if builtins.is_empty() && grammars.is_empty() {
+        return result;
+    }
+    let fuzzy = (*(*recog_channel).audio_buffer).config().fuzzy.as_ref();
+    let base = message::content_base(request);
+    let weight = |uri: &str| {
+        refs.iter()
+            .find(|grammar| grammar.uri == uri || fetch::resolve(&base, &grammar.uri) == uri)
+            .map_or(message::DEFAULT_GRAMMAR_WEIGHT, |grammar| grammar.weight)
+    };
+    let alternatives = result
+        .alternatives
+        .iter()
+        .filter_map(|alternative| {
+            let text = &alternative.text;
+            let builtin_matches = builtins
+                .iter()
+                .filter(|grammar| grammar.mode == builtin::Mode::Speech)
+                .filter_map(|grammar| Some((&grammar.uri, grammar.interpret(text)?, 1.0)));
+            let srgs_matches = grammars
+                .iter()
+                .filter(|grammar| grammar.mode == builtin::Mode::Speech)
+                .filter_map(|grammar| {
+                    let (instance, similarity) = match fuzzy {
+                        Some(matcher) => grammar.interpret_fuzzy(text, matcher)?,
+                        None => (grammar.interpret(text)?, 1.0),
+                    };
+                    Some((&grammar.uri, instance, similarity))
+                });
+            let mut best: Option<(f32, f32, &String, String)> = None;
+            for (uri, instance, similarity) in builtin_matches.chain(srgs_matches) {
+                let weight = weight(uri);
+                let better = match best {
+                    Some((best_similarity, best_weight, ..)) => {
+                        (similarity, weight) > (best_similarity, best_weight)
+                    }
+                    None => true,
+                };
+                if better {
+                    best = Some((similarity, weight, uri, instance));
+                }
+            }
+            let (similarity, weight, uri, instance) = best?;
+            if similarity < 1.0 {
+                log::info!("Fuzzy match {:?} in {:?} ({})", text, uri, similarity);
+            }
+            let alternative = Alternative {
+                confidence: alternative.confidence * similarity,
+                instance: Some(instance),
+                grammar: Some(uri.clone()),
+                weight,
+                ..alternative.clone()
+            };
+            Some(alternative)
+        })
+        .collect::<Vec<_>>();
+    RecogResult {
+        mode: result.mode,
+        alternatives,
+    }
+}
+
 unsafe fn rs_recog_recognition_process(
     recog_channel: *mut MrcpRecogChannel,
     recog_event: SpeechDetectorEvent,
 ) -> uni::apt_bool_t {
-    let mut recognized = String::new();
+    let mut recognized = RecogResult::default();
+    let speech_enabled = (*(*recog_channel).audio_buffer).speech_enabled();
     let cause = match recog_event {
         SpeechDetectorEvent::None => return uni::FALSE,
+        SpeechDetectorEvent::Activity if !speech_enabled => return uni::FALSE,
         SpeechDetectorEvent::Activity => {
             log::info!("Detected Voice Activity in {:?}", (*recog_channel).channel);
             if !(*(*recog_channel).audio_buffer).input_started() {
@@ -401,6 +1138,7 @@ unsafe fn rs_recog_recognition_process(
                 return uni::TRUE;
             }
         }
+        SpeechDetectorEvent::Inactivity { .. } if !speech_enabled => return uni::FALSE,
         SpeechDetectorEvent::Inactivity { duration } => {
             log::info!(
                 "Detected Voice {:?} in {:?}",
@@ -410,6 +1148,24 @@ unsafe fn rs_recog_recognition_process(
             (*(*recog_channel).audio_buffer).recognize(duration);
             return uni::TRUE;
         }
+        SpeechDetectorEvent::DurationTimeout if !speech_enabled => {
+            log::info!(
+                "Detected Duration Timeout waiting for DTMF in {:?}",
+                (*recog_channel).channel
+            );
+            let no_input = (*(*recog_channel).audio_buffer).dtmf_digits().is_empty();
+            recognized = (*(*recog_channel).audio_buffer).dtmf_finish();
+            if !no_input {
+                (*(*recog_channel).audio_buffer).keep_result(recognized.clone());
+            }
+            if no_input {
+                uni::RECOGNIZER_COMPLETION_CAUSE_NO_INPUT_TIMEOUT
+            } else if recognized.alternatives.is_empty() {
+                uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH
+            } else {
+                uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS
+            }
+        }
         SpeechDetectorEvent::DurationTimeout => {
             log::info!(
                 "Detected Duration Timeout in {:?}",
@@ -423,23 +1179,189 @@ unsafe fn rs_recog_recognition_process(
             log::info!("Detected Noinput. Channel {:?}", (*recog_channel).channel);
             uni::RECOGNIZER_COMPLETION_CAUSE_NO_INPUT_TIMEOUT
         }
+        SpeechDetectorEvent::Recognizing if (*(*recog_channel).audio_buffer).grammars_loading() => {
+            return uni::FALSE;
+        }
         SpeechDetectorEvent::Recognizing => match (*(*recog_channel).audio_buffer).load_result() {
             None => return uni::FALSE,
+            Some(result) if result.is_empty() => {
+                if message::recognition_mode((*recog_channel).recog_request)
+                    == RecognitionMode::Dictation
+                {
+                    (*(*recog_channel).audio_buffer).next_segment();
+                } else {
+                    (*(*recog_channel).audio_buffer).restart_writing();
+                }
+                return uni::FALSE;
+            }
             Some(result) => {
+                let (cause, result) = rs_recog_backend_result(recog_channel, result);
+                match message::recognition_mode((*recog_channel).recog_request) {
+                    RecognitionMode::Hotword
+                        if cause == uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH =>
+                    {
+                        log::info!(
+                            "No hotword heard, keep listening in {:?}",
+                            (*recog_channel).channel
+                        );
+                        (*(*recog_channel).audio_buffer).restart_writing();
+                        return uni::FALSE;
+                    }
+                    RecognitionMode::Dictation => {
+                        return rs_recog_dictation_segment(recog_channel, cause, &result);
+                    }
+                    _ => {}
+                }
                 recognized = result;
-                uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS
+                cause
             }
         },
     };
+    rs_recog_recognition_complete(recog_channel, cause, &recognized)
+}
+
+/// Delivers a dictation segment as an INTERMEDIATE-RESULT and keeps listening.
+unsafe fn rs_recog_dictation_segment(
+    recog_channel: *mut MrcpRecogChannel,
+    cause: uni::mrcp_recog_completion_cause_e,
+    recognized: &RecogResult,
+) -> uni::apt_bool_t {
+    (*(*recog_channel).audio_buffer).next_segment();
+    if cause != uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS {
+        return uni::FALSE;
+    }
+    if let Some(best) = recognized.best() {
+        (*(*recog_channel).audio_buffer).sink_segment(
+            message::apt_string(&(*(*recog_channel).channel).id),
+            best.text.clone(),
+        );
+    }
     let message = uni::mrcp_event_create(
         (*recog_channel).recog_request,
-        uni::RECOGNIZER_RECOGNITION_COMPLETE as _,
+        uni::RECOGNIZER_INTERMEDIATE_RESULT as _,
         (*(*recog_channel).recog_request).pool,
     );
     if message.is_null() {
-        log::error!("Unable to create event RECOGNITION COMPLETE");
+        log::error!("Unable to create event INTERMEDIATE RESULT for dictation segment");
         return uni::FALSE;
     }
+    (*message).start_line.request_state = uni::MRCP_REQUEST_STATE_INPROGRESS;
+    rs_recog_result_load(recog_channel, recognized, message);
+    log::info!(
+        "Send dictation segment {:?} in {:?}",
+        recognized,
+        (*recog_channel).channel
+    );
+    inline_mrcp_engine_channel_message_send((*recog_channel).channel, message)
+}
+
+/// Applies the request grammars and thresholds to a backend result.
+unsafe fn rs_recog_backend_result(
+    recog_channel: *mut MrcpRecogChannel,
+    result: RecogResult,
+) -> (uni::mrcp_recog_completion_cause_e, RecogResult) {
+    let request = (*recog_channel).recog_request;
+    let audio_buffer = &*(*recog_channel).audio_buffer;
+    let (grammars, lexicons) = (audio_buffer.grammars(), audio_buffer.lexicons());
+    let result = rs_recog_grammar_interpret(recog_channel, request, grammars, lexicons, result)
+        .attributed(message::grammar_uri(request));
+    (*(*recog_channel).audio_buffer).keep_result(result.clone());
+    let recognized = result.filtered(
+        message::confidence_threshold(request).unwrap_or(result::DEFAULT_CONFIDENCE_THRESHOLD),
+        message::n_best_list_length(request).unwrap_or(result::DEFAULT_N_BEST_LIST_LENGTH),
+    );
+    if recognized.alternatives.is_empty() {
+        log::info!(
+            "No hypothesis matched grammars or Confidence-Threshold in {:?}: {:?}",
+            (*recog_channel).channel,
+            result
+        );
+        (uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH, recognized)
+    } else {
+        (uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS, recognized)
+    }
+}
+
+/// Completes the RECOGNIZE cut by STOP once the backend answers, then
+/// responds to the STOP. If the backend takes too long, the STOP is
+/// answered without a result.
+unsafe fn rs_recog_stop_process(recog_channel: *mut MrcpRecogChannel) -> uni::apt_bool_t {
+    // Picks up the grammars the partial utterance waits for.
+    let failure = (*(*recog_channel).audio_buffer).failure();
+    let result = match failure {
+        Some(failure) => {
+            log::warn!("Partial utterance fails with {:?}", failure);
+            None
+        }
+        None => (*(*recog_channel).audio_buffer).load_result(),
+    };
+    let Some(result) = result else {
+        if failure.is_none() {
+            if !(*(*recog_channel).audio_buffer).stop_expired() {
+                return uni::FALSE;
+            }
+            log::warn!(
+                "No result of the partial utterance in time, stop {:?}",
+                (*recog_channel).channel
+            );
+        }
+        let response = std::mem::replace(
+            &mut (*recog_channel).stop_response,
+            std::ptr::null_mut() as _,
+        );
+        return rs_recog_stop_respond(recog_channel, response);
+    };
+    let (cause, recognized) = rs_recog_backend_result(recog_channel, result);
+    rs_recog_recognition_complete(recog_channel, cause, &recognized);
+    let response = std::mem::replace(
+        &mut (*recog_channel).stop_response,
+        std::ptr::null_mut() as _,
+    );
+    rs_recog_stop_respond(recog_channel, response)
+}
+
+unsafe fn rs_recog_dtmf_process(recog_channel: *mut MrcpRecogChannel) -> uni::apt_bool_t {
+    match (*(*recog_channel).audio_buffer).dtmf_event() {
+        DtmfEvent::None => uni::FALSE,
+        DtmfEvent::Input => {
+            log::info!(
+                "Detected DTMF input {:?} in {:?}",
+                (*(*recog_channel).audio_buffer).dtmf_digits(),
+                (*recog_channel).channel
+            );
+            if (*(*recog_channel).audio_buffer).speech_enabled() {
+                log::info!(
+                    "DTMF barge-in, drop speech input in {:?}",
+                    (*recog_channel).channel
+                );
+                (*(*recog_channel).audio_buffer).switch_to_dtmf();
+            }
+            if !(*(*recog_channel).audio_buffer).input_started() {
+                (*(*recog_channel).audio_buffer).start_input();
+                return rs_recog_start_of_input(recog_channel);
+            }
+            uni::TRUE
+        }
+        DtmfEvent::Complete(recognized) => {
+            if !(*(*recog_channel).audio_buffer).input_started() {
+                (*(*recog_channel).audio_buffer).start_input();
+                rs_recog_start_of_input(recog_channel);
+            }
+            (*(*recog_channel).audio_buffer).keep_result(recognized.clone());
+            let cause = if recognized.alternatives.is_empty() {
+                uni::RECOGNIZER_COMPLETION_CAUSE_NO_MATCH
+            } else {
+                uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS
+            };
+            rs_recog_recognition_complete(recog_channel, cause, &recognized)
+        }
+    }
+}
+
+unsafe fn rs_recog_completion_cause_set(
+    message: *mut uni::mrcp_message_t,
+    cause: uni::mrcp_recog_completion_cause_e,
+) {
     let recog_header =
         inline_mrcp_resource_header_prepare(message) as *mut uni::mrcp_recog_header_t;
     if !recog_header.is_null() {
@@ -449,22 +1371,109 @@ unsafe fn rs_recog_recognition_process(
             uni::RECOGNIZER_HEADER_COMPLETION_CAUSE as _,
         );
     }
+}
+
+unsafe fn rs_recog_recognition_complete(
+    recog_channel: *mut MrcpRecogChannel,
+    cause: uni::mrcp_recog_completion_cause_e,
+    recognized: &RecogResult,
+) -> uni::apt_bool_t {
+    let message = uni::mrcp_event_create(
+        (*recog_channel).recog_request,
+        uni::RECOGNIZER_RECOGNITION_COMPLETE as _,
+        (*(*recog_channel).recog_request).pool,
+    );
+    if message.is_null() {
+        log::error!("Unable to create event RECOGNITION COMPLETE");
+        return uni::FALSE;
+    }
+    rs_recog_completion_cause_set(message, cause);
     (*message).start_line.request_state = uni::MRCP_REQUEST_STATE_COMPLETE;
     if cause == uni::RECOGNIZER_COMPLETION_CAUSE_SUCCESS {
-        if recognized.is_empty() {
-            (*(*recog_channel).audio_buffer).restart_writing();
-            return uni::FALSE;
-        }
-        rs_recog_result_load(recognized.as_str(), message);
+        rs_recog_result_load(recog_channel, recognized, message);
         log::info!(
-            "Load for {:?}: {:?} ({} bytes)",
+            "Load for {:?}: {:?} ({} alternatives)",
             (*recog_channel).channel,
             recognized,
-            recognized.as_bytes().len()
+            recognized.alternatives.len()
         );
     }
+    rs_recog_waveform_save(recog_channel, message);
+    (*(*recog_channel).audio_buffer).complete();
     (*recog_channel).recog_request = std::ptr::null_mut() as _;
-    inline_mrcp_engine_channel_message_send((*recog_channel).channel, message)
+    let sent = inline_mrcp_engine_channel_message_send((*recog_channel).channel, message);
+    if (*recog_channel).state == ChannelState::Stopping {
+        return sent;
+    }
+    (*recog_channel).state = ChannelState::Recognized;
+    let queued = std::mem::replace(
+        &mut (*recog_channel).queued_request,
+        std::ptr::null_mut() as _,
+    );
+    if !queued.is_null() {
+        log::info!(
+            "Start queued RECOGNIZE {:?} in {:?}",
+            queued,
+            (*recog_channel).channel
+        );
+        rs_recog_recognition_start(recog_channel, queued);
+    }
+    sent
+}
+
+/// Saves the utterance of a RECOGNIZE with Save-Waveform and reports it
+/// in the Waveform-URI header of `message`.
+unsafe fn rs_recog_waveform_save(
+    recog_channel: *mut MrcpRecogChannel,
+    message: *mut uni::mrcp_message_t,
+) {
+    let request = (*recog_channel).recog_request;
+    if !message::save_waveform(request) {
+        return;
+    }
+    let media_type = match message::media_type(request) {
+        Some(value) => MediaType::parse(&value).unwrap_or_else(|| {
+            log::warn!("Unsupported Media-Type {:?}, save as WAV", value);
+            MediaType::Wav
+        }),
+        None => MediaType::default(),
+    };
+    let dir = &(*(*recog_channel).audio_buffer).config().waveform_dir;
+    let record_uri = message::vendor_params(request).remove(RECORD_URI_PARAM);
+    let waveform = record_uri
+        .and_then(|uri| {
+            Waveform::from_uri(dir, &uri, media_type).or_else(|| {
+                log::warn!("Refused {} {:?} outside {:?}", RECORD_URI_PARAM, uri, dir);
+                None
+            })
+        })
+        .unwrap_or_else(|| {
+            Waveform::new(
+                dir,
+                &message::apt_string(&(*(*recog_channel).channel).id),
+                (*request).start_line.request_id,
+                media_type,
+            )
+        });
+    let Some(waveform_uri) = (*(*recog_channel).audio_buffer).save_waveform(waveform) else {
+        return;
+    };
+    log::info!(
+        "Waveform-URI {} in {:?}",
+        waveform_uri,
+        (*recog_channel).channel
+    );
+    let recog_header =
+        inline_mrcp_resource_header_prepare(message) as *mut uni::mrcp_recog_header_t;
+    if !recog_header.is_null() {
+        let waveform_uri = format!("{}\0", waveform_uri);
+        inline_apt_string_assign(
+            &mut (*recog_header).waveform_uri as _,
+            waveform_uri.as_ptr() as _,
+            (*message).pool,
+        );
+        uni::mrcp_resource_header_property_add(message, uni::RECOGNIZER_HEADER_WAVEFORM_URI as _);
+    }
 }
 
 pub unsafe extern "C" fn stream_write(
@@ -472,40 +1481,108 @@ pub unsafe extern "C" fn stream_write(
     frame: *const uni::mpf_frame_t,
 ) -> uni::apt_bool_t {
     let custom_channel = (*stream).obj as *mut MrcpRecogChannel;
-    if !(*custom_channel).stop_response.is_null() {
-        inline_mrcp_engine_channel_message_send(
-            (*custom_channel).channel,
-            (*custom_channel).stop_response,
-        );
-        (*custom_channel).stop_response = std::ptr::null_mut() as _;
-        (*custom_channel).recog_request = std::ptr::null_mut() as _;
-        return uni::TRUE;
-    }
-    if !(*custom_channel).recog_request.is_null() {
-        if ((*frame).type_ & (uni::MEDIA_FRAME_TYPE_EVENT as i32))
-            == uni::MEDIA_FRAME_TYPE_EVENT as i32
-        {
-            if (*frame).marker == uni::MPF_MARKER_START_OF_EVENT as i32 {
-                log::info!(
-                    "Detected Start of Event id: {}",
-                    (*frame).event_frame.event_id()
-                );
-            } else if (*frame).marker == uni::MPF_MARKER_END_OF_EVENT as i32 {
-                log::info!(
-                    "Detected End of Event id: {}, duration: {}",
-                    (*frame).event_frame.event_id(),
-                    (*frame).event_frame.duration()
-                )
+    let _guard = rs_recog_channel_lock(custom_channel);
+    if ((*frame).type_ & (uni::MEDIA_FRAME_TYPE_EVENT as i32)) == uni::MEDIA_FRAME_TYPE_EVENT as i32
+    {
+        (*(*custom_channel).audio_buffer).dtmf_tick();
+        if (*frame).marker == uni::MPF_MARKER_START_OF_EVENT as i32 {
+            log::info!(
+                "Detected Start of Event id: {}",
+                (*frame).event_frame.event_id()
+            );
+            if let Some(key) = dtmf::digit((*frame).event_frame.event_id() as _) {
+                (*(*custom_channel).audio_buffer).dtmf_key(key);
             }
-        } else {
+        } else if (*frame).marker == uni::MPF_MARKER_END_OF_EVENT as i32 {
+            log::info!(
+                "Detected End of Event id: {}, duration: {}",
+                (*frame).event_frame.event_id(),
+                (*frame).event_frame.duration()
+            )
+        }
+    } else if (*custom_channel).state == ChannelState::Stopping {
+        (*(*custom_channel).audio_buffer).dtmf_tick();
+    } else if !(*custom_channel).recog_request.is_null() {
+        if !rs_recog_failure_process(custom_channel) && (*(*custom_channel).audio_buffer).live() {
             let buf = std::slice::from_raw_parts(
                 (*frame).codec_frame.buffer as *mut u8,
                 (*frame).codec_frame.size,
             );
             (*(*custom_channel).audio_buffer).write(buf).ok();
-            let event = (*(*custom_channel).audio_buffer).detector_event();
+            rs_recog_frame_process(custom_channel);
+        }
+    } else {
+        (*(*custom_channel).audio_buffer).dtmf_tick();
+    }
+    if !(*custom_channel).recog_request.is_null() {
+        rs_recog_dtmf_process(custom_channel);
+    }
+    uni::TRUE
+}
+
+/// Completes the RECOGNIZE in progress if its background work failed.
+unsafe fn rs_recog_failure_process(recog_channel: *mut MrcpRecogChannel) -> bool {
+    let Some(failure) = (*(*recog_channel).audio_buffer).failure() else {
+        return false;
+    };
+    let cause = match failure {
+        Failure::InputWaveform => uni::RECOGNIZER_COMPLETION_CAUSE_URI_FAILURE,
+        Failure::LanguageUnsupported => uni::RECOGNIZER_COMPLETION_CAUSE_LANGUAGE_UNSUPPORTED,
+        Failure::GrammarLoad => uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_LOAD_FAILURE,
+        Failure::GrammarCompile => uni::RECOGNIZER_COMPLETION_CAUSE_GRAM_COMP_FAILURE,
+    };
+    rs_recog_recognition_complete(recog_channel, cause, &RecogResult::default());
+    true
+}
+
+/// Recognizes a batch of frames of the Input-Waveform-URI audio, and wakes
+/// the engine task for the next batch unless the backend is awaited.
+unsafe fn rs_recog_waveform_process(recog_channel: *mut MrcpRecogChannel) {
+    for _ in 0..WAVEFORM_FRAMES_PER_POLL {
+        if (*recog_channel).state != ChannelState::Recognizing
+            || (*(*recog_channel).audio_buffer).detector_event() == SpeechDetectorEvent::Recognizing
+            || !(*(*recog_channel).audio_buffer).pump_waveform()
+        {
+            return;
+        }
+        rs_recog_frame_process(recog_channel);
+        if !(*recog_channel).recog_request.is_null() {
+            rs_recog_dtmf_process(recog_channel);
+        }
+    }
+    (*(*recog_channel).audio_buffer).wakeup().wake();
+}
+
+/// Acts on the speech detected in the frame just written. A key heard in
+/// the same frame barges in first, so none of its speech reaches the
+/// backend.
+unsafe fn rs_recog_frame_process(recog_channel: *mut MrcpRecogChannel) {
+    let barge_in = (*(*recog_channel).audio_buffer).speech_enabled()
+        && (*(*recog_channel).audio_buffer).dtmf_event() == DtmfEvent::Input;
+    if !barge_in {
+        let event = (*(*recog_channel).audio_buffer).detector_event();
+        rs_recog_recognition_process(recog_channel, event);
+    }
+}
+
+/// Picks up the background work of the channel that is done.
+unsafe fn rs_recog_channel_poll(channel: *mut uni::mrcp_engine_channel_t) -> uni::apt_bool_t {
+    let custom_channel = (*channel).method_obj as *mut MrcpRecogChannel;
+    let _guard = rs_recog_channel_lock(custom_channel);
+    if (*custom_channel).state == ChannelState::Stopping {
+        rs_recog_stop_process(custom_channel);
+    }
+    if (*custom_channel).state == ChannelState::Recognizing
+        && !rs_recog_failure_process(custom_channel)
+    {
+        let event = (*(*custom_channel).audio_buffer).detector_event();
+        if event == SpeechDetectorEvent::Recognizing {
             rs_recog_recognition_process(custom_channel, event);
         }
+        rs_recog_waveform_process(custom_channel);
+    }
+    if !(*custom_channel).interpret_request.is_null() {
+        rs_recog_interpretation_complete(custom_channel);
     }
     uni::TRUE
 }
@@ -546,6 +1623,9 @@ unsafe extern "C" fn rs_recog_msg_process(
         RecogMsgType::RequestProcess => {
             rs_recog_channel_request_dispatch((*recog_msg).channel, (*recog_msg).request);
         }
+        RecogMsgType::Poll => {
+            rs_recog_channel_poll((*recog_msg).channel);
+        }
     }
     uni::TRUE
 }
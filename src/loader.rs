@@ -0,0 +1,235 @@
+use crate::fetch::{self, FetchPolicy};
+use crate::lexicon::Lexicon;
+use crate::srgs::Grammar;
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
+};
+
+/// Age of a cached grammar the loader accepts without Cache-Control.
+pub const DEFAULT_MAX_AGE: u64 = 3600;
+/// Grammars, and lexicons, the cache keeps at most.
+const MAX_CACHE_ENTRIES: usize = 1000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum LoadError {
+    Fetch(String),
+    Compile(String),
+}
+
+/// Request directives of the Cache-Control header.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CacheControl {
+    pub max_age: u64,
+    pub max_stale: u64,
+}
+
+impl Default for CacheControl {
+    fn default() -> Self {
+        Self {
+            max_age: DEFAULT_MAX_AGE,
+            max_stale: 0,
+        }
+    }
+}
+
+impl CacheControl {
+    /// Parses directives like `max-age=60, max-stale=10` or `no-cache`.
+    pub fn parse(value: &str) -> Self {
+        let mut control = Self::default();
+        for directive in value.split(',') {
+            let (name, value) = match directive.split_once('=') {
+                Some((name, value)) => (name.trim(), value.trim().parse().ok()),
+                None => (directive.trim(), None),
+            };
+            match (name.to_ascii_lowercase().as_str(), value) {
+                ("max-age", Some(seconds)) => control.max_age = seconds,
+                ("max-stale", Some(seconds)) => control.max_stale = seconds,
+                ("no-cache", _) => control.max_age = 0,
+                _ => log::warn!("Ignore Cache-Control directive {:?}", directive),
+            }
+        }
+        control
+    }
+
+    fn accepts(&self, age: Duration) -> bool {
+        age < Duration::from_secs(self.max_age.saturating_add(self.max_stale))
+    }
+}
+
+#[derive(Debug)]
+struct CacheEntry<T> {
+    value: Arc<T>,
+    loaded: Instant,
+}
+
+type Entries<T> = Arc<Mutex<HashMap<String, CacheEntry<T>>>>;
+
+fn lookup<T>(entries: &Entries<T>, uri: &str, control: &CacheControl) -> Option<Arc<T>> {
+    let entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+    entries
+        .get(uri)
+        .filter(|entry| control.accepts(entry.loaded.elapsed()))
+        .map(|entry| entry.value.clone())
+}
+
+/// Caches `value`. A full cache first drops the entries older than the
+/// default max age, then the oldest one.
+fn store<T>(entries: &Entries<T>, uri: &str, value: Arc<T>) {
+    let mut entries = entries.lock().unwrap_or_else(PoisonError::into_inner);
+    if entries.len() >= MAX_CACHE_ENTRIES && !entries.contains_key(uri) {
+        let max_age = Duration::from_secs(DEFAULT_MAX_AGE);
+        entries.retain(|_, entry| entry.loaded.elapsed() < max_age);
+        let oldest = entries
+            .iter()
+            .min_by_key(|(_, entry)| entry.loaded)
+            .map(|(uri, _)| uri.clone());
+        if let Some(oldest) = oldest.filter(|_| entries.len() >= MAX_CACHE_ENTRIES) {
+            entries.remove(&oldest);
+        }
+    }
+    let entry = CacheEntry {
+        value,
+        loaded: Instant::now(),
+    };
+    entries.insert(uri.to_owned(), entry);
+}
+
+/// Compiled grammars and lexicons shared by all channels of the engine.
+#[derive(Debug, Clone, Default)]
+pub struct GrammarCache {
+    grammars: Entries<Grammar>,
+    lexicons: Entries<Lexicon>,
+}
+
+impl GrammarCache {
+    pub fn leaked() -> *mut Self {
+        Box::into_raw(Box::default())
+    }
+
+    pub unsafe fn destroy(this: *mut Self) {
+        if !this.is_null() {
+            drop(Box::from_raw(this));
+        }
+    }
+
+    pub fn get(&self, uri: &str, control: &CacheControl) -> Option<Arc<Grammar>> {
+        lookup(&self.grammars, uri, control)
+    }
+
+    pub fn lexicon(&self, uri: &str, control: &CacheControl) -> Option<Arc<Lexicon>> {
+        lookup(&self.lexicons, uri, control)
+    }
+}
+
+/// Grammars of a request and the lexicons they and the request reference.
+#[derive(Debug, Clone, Default)]
+pub struct Loaded {
+    pub grammars: Vec<Arc<Grammar>>,
+    pub lexicons: Vec<Arc<Lexicon>>,
+}
+
+/// URIs of `lexicons` and of those referenced by `grammars`, once each.
+pub fn lexicon_uris(grammars: &[Arc<Grammar>], lexicons: &[String]) -> Vec<String> {
+    let mut uris: Vec<String> = vec![];
+    let referenced = grammars.iter().flat_map(|grammar| grammar.lexicons.iter());
+    for uri in lexicons.iter().chain(referenced) {
+        if !uris.contains(uri) {
+            uris.push(uri.clone());
+        }
+    }
+    uris
+}
+
+/// How the resources not at hand are fetched.
+pub struct FetchParams {
+    pub timeout: usize,
+    pub policy: FetchPolicy,
+}
+
+/// Grammars of `uris` in order and the lexicons of the request. Those
+/// found neither among the `session` grammars nor in the cache are fetched
+/// and compiled.
+pub async fn load(
+    cache: GrammarCache,
+    session: HashMap<String, Arc<Grammar>>,
+    uris: Vec<String>,
+    lexicons: Vec<String>,
+    fetch_params: FetchParams,
+    control: CacheControl,
+) -> Result<Loaded, LoadError> {
+    let mut loaded = Loaded::default();
+    for uri in uris {
+        if let Some(grammar) = session.get(&uri) {
+            loaded.grammars.push(grammar.clone());
+            continue;
+        }
+        if let Some(grammar) = cache.get(&uri, &control) {
+            loaded.grammars.push(grammar);
+            continue;
+        }
+        let source = fetch::fetch(&uri, fetch_params.timeout, &fetch_params.policy)
+            .await
+            .map_err(LoadError::Fetch)?;
+        let source = String::from_utf8_lossy(&source);
+        let grammar = Arc::new(Grammar::compile(&uri, &source).map_err(LoadError::Compile)?);
+        log::info!("Loaded grammar {:?}", uri);
+        store(&cache.grammars, &uri, grammar.clone());
+        loaded.grammars.push(grammar);
+    }
+    for uri in lexicon_uris(&loaded.grammars, &lexicons) {
+        if let Some(lexicon) = cache.lexicon(&uri, &control) {
+            loaded.lexicons.push(lexicon);
+            continue;
+        }
+        let source = fetch::fetch(&uri, fetch_params.timeout, &fetch_params.policy)
+            .await
+            .map_err(LoadError::Fetch)?;
+        let source = String::from_utf8_lossy(&source);
+        let lexicon = Arc::new(Lexicon::compile(&uri, &source).map_err(LoadError::Compile)?);
+        log::info!("Loaded lexicon {:?}", uri);
+        store(&cache.lexicons, &uri, lexicon.clone());
+        loaded.lexicons.push(lexicon);
+    }
+    Ok(loaded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cache_control() {
+        assert_eq!(CacheControl::parse(""), CacheControl::default());
+        let control = CacheControl::parse("max-age=60, MAX-STALE = 10");
+        assert_eq!((control.max_age, control.max_stale), (60, 10));
+        assert_eq!(CacheControl::parse("no-cache").max_age, 0);
+    }
+
+    #[test]
+    fn ignores_invalid_directives() {
+        for value in [
+            "max-age",
+            "max-age=-1",
+            "max-age=soon",
+            "private",
+            "max-stale=",
+        ] {
+            assert_eq!(
+                CacheControl::parse(value),
+                CacheControl::default(),
+                "{}",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn accepts_stale_entries_within_max_stale() {
+        let control = CacheControl::parse("max-age=60, max-stale=10");
+        assert!(control.accepts(Duration::from_secs(69)));
+        assert!(!control.accepts(Duration::from_secs(70)));
+        assert!(!CacheControl::parse("no-cache").accepts(Duration::ZERO));
+    }
+}
@@ -0,0 +1,317 @@
+use crate::dtmf::DtmfParams;
+use rsunimrcp_sys::uni;
+use std::collections::HashMap;
+
+pub unsafe fn apt_string(s: &uni::apt_str_t) -> String {
+    if s.buf.is_null() || s.length == 0 {
+        return String::new();
+    }
+    let bytes = std::slice::from_raw_parts(s.buf as *const u8, s.length);
+    String::from_utf8_lossy(bytes).into_owned()
+}
+
+pub unsafe fn generic_header(
+    message: *const uni::mrcp_message_t,
+) -> *mut uni::mrcp_generic_header_t {
+    (*message).header.generic_header_accessor.data as _
+}
+
+pub unsafe fn body(message: *const uni::mrcp_message_t) -> String {
+    apt_string(&(*message).body)
+}
+
+pub unsafe fn content_type(message: *const uni::mrcp_message_t) -> String {
+    let header = generic_header(message);
+    if header.is_null() {
+        return String::new();
+    }
+    apt_string(&(*header).content_type)
+}
+
+/// Whether the body of a message with `content_type` lists grammar URIs.
+pub fn is_uri_list(content_type: &str) -> bool {
+    content_type.starts_with("text/uri-list") || content_type.starts_with("text/grammar-ref-list")
+}
+
+pub unsafe fn content_id(message: *const uni::mrcp_message_t) -> String {
+    let header = generic_header(message);
+    if header.is_null() {
+        return String::new();
+    }
+    apt_string(&(*header).content_id)
+}
+
+pub unsafe fn content_base(message: *const uni::mrcp_message_t) -> String {
+    let header = generic_header(message);
+    if header.is_null() {
+        return String::new();
+    }
+    apt_string(&(*header).content_base)
+}
+
+pub unsafe fn cache_control(message: *const uni::mrcp_message_t) -> Option<String> {
+    let header = generic_header(message);
+    if header.is_null() {
+        return None;
+    }
+    Some(apt_string(&(*header).cache_control)).filter(|value| !value.trim().is_empty())
+}
+
+/// Vendor-Specific-Parameters of a message as name/value pairs.
+pub unsafe fn vendor_params(message: *const uni::mrcp_message_t) -> HashMap<String, String> {
+    let header = generic_header(message);
+    if header.is_null() || (*header).vendor_specific_params.is_null() {
+        return HashMap::new();
+    }
+    let params = (*header).vendor_specific_params;
+    let pairs = (*params).elts as *const uni::apt_pair_t;
+    (0..(*params).nelts as usize)
+        .map(|i| {
+            let pair = &*pairs.add(i);
+            (apt_string(&pair.name), apt_string(&pair.value))
+        })
+        .collect()
+}
+
+pub const DEFAULT_GRAMMAR_WEIGHT: f32 = 1.0;
+
+/// Grammar activated by a request with its `;weight=`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GrammarRef {
+    pub uri: String,
+    pub weight: f32,
+}
+
+impl GrammarRef {
+    /// Parses `<uri>;weight="0.5"` as well as a bare `uri;weight=0.5`.
+    fn parse(line: &str) -> Self {
+        let (uri, params) = match line.strip_prefix('<') {
+            Some(rest) => rest.split_once('>').unwrap_or((rest, "")),
+            None => match line.to_ascii_lowercase().find(";weight=") {
+                Some(i) => line.split_at(i),
+                None => (line, ""),
+            },
+        };
+        let weight = params
+            .split(';')
+            .filter_map(|param| param.split_once('='))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("weight"))
+            .and_then(|(_, value)| value.trim().trim_matches('"').parse::<f32>().ok())
+            .filter(|weight| *weight >= 0.0)
+            .unwrap_or(DEFAULT_GRAMMAR_WEIGHT);
+        Self {
+            uri: uri.trim().to_owned(),
+            weight,
+        }
+    }
+}
+
+/// Grammars referenced by a RECOGNIZE request.
+pub unsafe fn grammar_refs(request: *const uni::mrcp_message_t) -> Vec<GrammarRef> {
+    if is_uri_list(&content_type(request)) {
+        body(request)
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(GrammarRef::parse)
+            .collect()
+    } else {
+        let id = content_id(request);
+        let id = id.trim_matches(|c| c == '<' || c == '>');
+        if id.is_empty() {
+            vec![]
+        } else {
+            vec![GrammarRef {
+                uri: format!("session:{}", id),
+                weight: DEFAULT_GRAMMAR_WEIGHT,
+            }]
+        }
+    }
+}
+
+/// URIs of the grammars referenced by a RECOGNIZE request.
+pub unsafe fn grammar_uris(request: *const uni::mrcp_message_t) -> Vec<String> {
+    grammar_refs(request)
+        .into_iter()
+        .map(|grammar| grammar.uri)
+        .collect()
+}
+
+/// URI of the first grammar referenced by a RECOGNIZE request.
+pub unsafe fn grammar_uri(request: *const uni::mrcp_message_t) -> Option<String> {
+    grammar_uris(request).into_iter().next()
+}
+
+pub unsafe fn recog_header(message: *const uni::mrcp_message_t) -> *mut uni::mrcp_recog_header_t {
+    (*message).header.resource_header_accessor.data as _
+}
+
+pub unsafe fn recog_header_present(message: *const uni::mrcp_message_t, id: usize) -> bool {
+    let section = &(*message).header.header_section;
+    let index = id + uni::GENERIC_HEADER_COUNT as usize;
+    index < section.arr_size && !section.arr.is_null() && !(*section.arr.add(index)).is_null()
+}
+
+/// Value of a recognizer header field, if the message carries the header.
+pub unsafe fn recog_header_field<T>(
+    message: *const uni::mrcp_message_t,
+    id: usize,
+    field: impl FnOnce(&uni::mrcp_recog_header_t) -> T,
+) -> Option<T> {
+    let header = recog_header(message);
+    if header.is_null() || !recog_header_present(message, id) {
+        return None;
+    }
+    Some(field(&*header))
+}
+
+pub unsafe fn confidence_threshold(message: *const uni::mrcp_message_t) -> Option<f32> {
+    let threshold = recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_CONFIDENCE_THRESHOLD as _,
+        |header| header.confidence_threshold,
+    )?;
+    // MRCPv1 clients express confidence as 0..100.
+    Some(if threshold > 1.0 {
+        threshold / 100.0
+    } else {
+        threshold
+    })
+}
+
+pub unsafe fn n_best_list_length(message: *const uni::mrcp_message_t) -> Option<usize> {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_N_BEST_LIST_LENGTH as _,
+        |header| header.n_best_list_length,
+    )
+}
+
+pub unsafe fn cancel_if_queue(message: *const uni::mrcp_message_t) -> bool {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_CANCEL_IF_QUEUE as _,
+        |header| header.cancel_if_queue == uni::TRUE,
+    )
+    .unwrap_or(false)
+}
+
+/// Vendor-specific parameter of RECOGNIZE that turns on dictation.
+pub const DICTATION_PARAM: &str = "dictation";
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RecognitionMode {
+    Normal,
+    Hotword,
+    /// Long-lived RECOGNIZE that reports every speech segment until STOP.
+    Dictation,
+}
+
+/// Dictation if the vendor-specific parameter asks for it, otherwise the
+/// Recognition-Mode header.
+pub unsafe fn recognition_mode(message: *const uni::mrcp_message_t) -> RecognitionMode {
+    if let Some(value) = vendor_params(message).get(DICTATION_PARAM) {
+        match crate::config::parse_bool(value) {
+            Some(true) => return RecognitionMode::Dictation,
+            Some(false) => {}
+            None => log::warn!("Invalid {} {:?}", DICTATION_PARAM, value),
+        }
+    }
+    let mode = recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_RECOGNITION_MODE as _,
+        |header| apt_string(&header.recognition_mode),
+    )
+    .unwrap_or_default();
+    match mode.trim().to_ascii_lowercase().as_str() {
+        "hotword" => RecognitionMode::Hotword,
+        _ => RecognitionMode::Normal,
+    }
+}
+
+pub unsafe fn save_waveform(message: *const uni::mrcp_message_t) -> bool {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_SAVE_WAVEFORM as _,
+        |header| header.save_waveform == uni::TRUE,
+    )
+    .unwrap_or(false)
+}
+
+pub unsafe fn media_type(message: *const uni::mrcp_message_t) -> Option<String> {
+    recog_header_field(message, uni::RECOGNIZER_HEADER_MEDIA_TYPE as _, |header| {
+        apt_string(&header.media_type)
+    })
+}
+
+pub unsafe fn input_waveform_uri(message: *const uni::mrcp_message_t) -> Option<String> {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_INPUT_WAVEFORM_URI as _,
+        |header| apt_string(&header.input_waveform_uri),
+    )
+    .map(|uri| uri.trim().trim_matches(|c| c == '<' || c == '>').to_owned())
+    .filter(|uri| !uri.is_empty())
+}
+
+pub unsafe fn speech_language(message: *const uni::mrcp_message_t) -> Option<String> {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_SPEECH_LANGUAGE as _,
+        |header| apt_string(&header.speech_language),
+    )
+    .filter(|language| !language.trim().is_empty())
+}
+
+pub unsafe fn fetch_timeout(message: *const uni::mrcp_message_t) -> Option<usize> {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_FETCH_TIMEOUT as _,
+        |header| header.fetch_timeout,
+    )
+}
+
+pub unsafe fn interpret_text(message: *const uni::mrcp_message_t) -> Option<String> {
+    recog_header_field(
+        message,
+        uni::RECOGNIZER_HEADER_INTERPRET_TEXT as _,
+        |header| apt_string(&header.interpret_text),
+    )
+    .filter(|text| !text.trim().is_empty())
+}
+
+pub unsafe fn dtmf_params(message: *const uni::mrcp_message_t) -> DtmfParams {
+    let defaults = DtmfParams::default();
+    DtmfParams {
+        interdigit_timeout: recog_header_field(
+            message,
+            uni::RECOGNIZER_HEADER_DTMF_INTERDIGIT_TIMEOUT as _,
+            |header| header.dtmf_interdigit_timeout,
+        )
+        .unwrap_or(defaults.interdigit_timeout),
+        term_timeout: recog_header_field(
+            message,
+            uni::RECOGNIZER_HEADER_DTMF_TERM_TIMEOUT as _,
+            |header| header.dtmf_term_timeout,
+        )
+        .unwrap_or(defaults.term_timeout),
+        term_char: recog_header_field(
+            message,
+            uni::RECOGNIZER_HEADER_DTMF_TERM_CHAR as _,
+            |header| header.dtmf_term_char as u8 as char,
+        )
+        .filter(|c| *c != '\0'),
+        buffer_time: recog_header_field(
+            message,
+            uni::RECOGNIZER_HEADER_DTMF_BUFFER_TIME as _,
+            |header| header.dtmf_buffer_time,
+        )
+        .unwrap_or(defaults.buffer_time),
+        clear_buffer: recog_header_field(
+            message,
+            uni::RECOGNIZER_HEADER_CLEAR_DTMF_BUFFER as _,
+            |header| header.clear_dtmf_buffer == uni::TRUE,
+        )
+        .unwrap_or(defaults.clear_buffer),
+    }
+}
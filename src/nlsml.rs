@@ -0,0 +1,117 @@
+use std::fmt::Write;
+
+pub const CONTENT_TYPE: &str = "application/nlsml+xml";
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    #[default]
+    Speech,
+    Dtmf,
+    /// Interpret-Text of INTERPRET.
+    Text,
+}
+
+impl InputMode {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Self::Speech => "speech",
+            Self::Dtmf => "dtmf",
+            Self::Text => "text",
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Interpretation<'a> {
+    pub grammar: Option<&'a str>,
+    pub confidence: f32,
+    pub mode: InputMode,
+    pub input: &'a str,
+    pub instance: &'a str,
+}
+
+pub fn result(interpretations: &[Interpretation]) -> String {
+    let mut xml = String::from("<?xml version=\"1.0\"?>\n");
+    xml.push_str("<result xmlns=\"urn:ietf:params:xml:ns:mrcpv2\">\n");
+    for interpretation in interpretations {
+        xml.push_str("  <interpretation");
+        if let Some(grammar) = interpretation.grammar {
+            let _ = write!(xml, " grammar=\"{}\"", escape(grammar));
+        }
+        let _ = writeln!(xml, " confidence=\"{:.2}\">", interpretation.confidence);
+        let _ = writeln!(
+            xml,
+            "    <instance>{}</instance>",
+            escape(interpretation.instance)
+        );
+        let _ = writeln!(
+            xml,
+            "    <input mode=\"{}\" confidence=\"{:.2}\">{}</input>",
+            interpretation.mode.as_str(),
+            interpretation.confidence,
+            escape(interpretation.input)
+        );
+        xml.push_str("  </interpretation>\n");
+    }
+    xml.push_str("</result>\n");
+    xml
+}
+
+pub fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn renders_interpretations() {
+        let xml = result(&[Interpretation {
+            grammar: Some("session:menu"),
+            confidence: 0.875,
+            mode: InputMode::Speech,
+            input: "yes",
+            instance: "yes",
+        }]);
+        assert_eq!(
+            xml,
+            "<?xml version=\"1.0\"?>\n\
+             <result xmlns=\"urn:ietf:params:xml:ns:mrcpv2\">\n  \
+             <interpretation grammar=\"session:menu\" confidence=\"0.88\">\n    \
+             <instance>yes</instance>\n    \
+             <input mode=\"speech\" confidence=\"0.88\">yes</input>\n  \
+             </interpretation>\n\
+             </result>\n"
+        );
+    }
+
+    #[test]
+    fn escapes_markup() {
+        assert_eq!(
+            escape("a<b & \"c\">'d'"),
+            "a&lt;b &amp; &quot;c&quot;&gt;&apos;d&apos;"
+        );
+        let xml = result(&[Interpretation {
+            grammar: None,
+            confidence: 1.0,
+            mode: InputMode::Speech,
+            input: "R&D",
+            instance: "<R&D/>",
+        }]);
+        assert!(xml.contains("<interpretation confidence=\"1.00\">"));
+        assert!(xml.contains("<instance>&lt;R&amp;D/&gt;</instance>"));
+        assert!(xml.contains(">R&amp;D</input>"));
+    }
+}
@@ -1,27 +1,216 @@
+use crate::builtin::{BuiltinGrammar, Mode};
+use crate::config::{ConfigStore, EngineConfig};
+use crate::dtmf::{DtmfCollector, DtmfEvent, DtmfGrammar, DtmfParams};
+use crate::fetch;
+use crate::language::Route;
+use crate::lexicon::{Lexicon, Pronunciation};
+use crate::loader::{self, CacheControl, GrammarCache, LoadError, Loaded};
+use crate::result::{Alternative, RecogResult};
 use crate::speech_detector::{Detector8kHz, SpeechDetectorEvent};
+use crate::srgs::Grammar;
+use crate::tone_detector::{self, ToneDetector};
+use crate::vendor::VendorParams;
+use crate::waveform::{self, Waveform};
 use rsunimrcp_engine::Engine;
 use rsunimrcp_sys::headers::RecogHeaders;
 use std::{
+    collections::{HashMap, HashSet},
     io::Write,
-    sync::{mpsc, Arc},
+    sync::{mpsc, Arc, Mutex, PoisonError},
+    time::{Duration, Instant},
 };
-use tokio::io::AsyncWriteExt;
+use tokio::{io::AsyncWriteExt, task::JoinHandle};
+
+/// Bytes of a frame of 16-bit LPCM at 8 kHz.
+const FRAME_BYTES: usize =
+    waveform::SAMPLE_RATE as usize / 1000 * 2 * rsunimrcp_sys::uni::CODEC_FRAME_TIME_BASE as usize;
+
+/// Phrases the backend should favour.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SpeechContext {
+    pub phrases: Vec<String>,
+    pub boost: Option<f32>,
+    pub pronunciations: Vec<Pronunciation>,
+}
+
+/// Utterance for the STT backend.
+#[derive(Debug)]
+struct BackendRequest {
+    audio: Vec<u8>,
+    context: SpeechContext,
+    route: Route,
+    params: VendorParams,
+    /// File the utterance is dumped to, if set, with its settings in
+    /// `<filename>.request`.
+    filename: String,
+}
+
+impl BackendRequest {
+    /// Settings of the utterance as `name: value` lines, one per hint.
+    fn settings(&self) -> String {
+        let mut lines = vec![
+            format!("backend: {}", self.route.backend),
+            format!("language: {}", self.route.language),
+        ];
+        for phrase in &self.context.phrases {
+            lines.push(format!("hint: {}", phrase));
+        }
+        if let Some(boost) = self.context.boost {
+            lines.push(format!("boost: {}", boost));
+        }
+        for pronunciation in &self.context.pronunciations {
+            lines.push(format!(
+                "pronunciation: {} /{}/ {}",
+                pronunciation.grapheme, pronunciation.phoneme, pronunciation.alphabet
+            ));
+        }
+        let params = &self.params;
+        if let Some(model) = &params.model {
+            lines.push(format!("model: {}", model));
+        }
+        if let Some(enabled) = params.profanity_filter {
+            lines.push(format!("profanity-filter: {}", enabled));
+        }
+        if let Some(enabled) = params.punctuation {
+            lines.push(format!("punctuation: {}", enabled));
+        }
+        let mut passthrough: Vec<_> = params.passthrough.iter().collect();
+        passthrough.sort();
+        for (key, value) in passthrough {
+            lines.push(format!("{}: {}", key, value));
+        }
+        lines.iter().map(|line| format!("{}\n", line)).collect()
+    }
+}
+
+#[derive(Debug)]
+enum AudioSource {
+    Live,
+    /// Input-Waveform-URI is being fetched.
+    Fetching(mpsc::Receiver<Result<Vec<u8>, String>>),
+    Waveform {
+        pcm: Vec<u8>,
+        position: usize,
+    },
+    Failed,
+}
+
+#[derive(Debug)]
+enum GrammarState {
+    Ready(Loaded),
+    Loading(mpsc::Receiver<Result<Loaded, LoadError>>),
+    Failed(LoadError),
+}
+
+type Wake = Box<dyn Fn() + Send>;
+
+/// Asks the engine task to poll the channel once background work is done.
+/// Cleared when the channel closes, so no poll outlives the channel.
+#[derive(Clone, Default)]
+pub struct Wakeup(Arc<Mutex<Option<Wake>>>);
+
+impl std::fmt::Debug for Wakeup {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Wakeup")
+    }
+}
+
+impl Wakeup {
+    pub fn set(&self, wake: impl Fn() + Send + 'static) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = Some(Box::new(wake));
+    }
+
+    pub fn clear(&self) {
+        *self.0.lock().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    pub fn wake(&self) {
+        if let Some(wake) = &*self.0.lock().unwrap_or_else(PoisonError::into_inner) {
+            wake();
+        }
+    }
+}
+
+/// Why a request cannot go on.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Failure {
+    InputWaveform,
+    LanguageUnsupported,
+    GrammarLoad,
+    GrammarCompile,
+}
 
 #[derive(Debug)]
 pub struct RecogBuffer {
     engine: Arc<Engine>,
+    store: ConfigStore,
+    /// Config of the request, kept while it goes on.
+    config: Arc<EngineConfig>,
+    /// Profile the channel attributes selected.
+    profile: Option<String>,
     speech_detector: Detector8kHz,
     speech_detector_event: SpeechDetectorEvent,
-    data_channel: (mpsc::Sender<String>, mpsc::Receiver<String>),
+    speech_enabled: bool,
+    /// Builtin grammars of the request.
+    builtins: Vec<BuiltinGrammar>,
+    dtmf: DtmfCollector,
+    tone_detector: ToneDetector,
+    data_channel: (mpsc::Sender<RecogResult>, mpsc::Receiver<RecogResult>),
+    backend_task: Option<JoinHandle<()>>,
+    /// Utterance held back until the grammars of the request are loaded.
+    deferred: Option<Vec<u8>>,
+    last_result: Option<RecogResult>,
+    recording: Option<Vec<u8>>,
+    source: AudioSource,
+    grammar_cache: GrammarCache,
+    session_grammars: HashMap<String, Arc<Grammar>>,
+    grammars: GrammarState,
+    /// Grammars of the INTERPRET in progress.
+    interpretation: Option<mpsc::Receiver<Result<Loaded, LoadError>>>,
+    wakeup: Wakeup,
+    /// Time the recognition of a partial utterance on STOP is given up.
+    stop_deadline: Option<Instant>,
+    hints: SpeechContext,
+    route: Option<Route>,
+    /// Vendor-specific parameters of SET-PARAMS.
+    session_params: VendorParams,
+    /// Vendor-specific parameters of the request.
+    params: VendorParams,
 }
 
 impl RecogBuffer {
-    pub fn leaked(engine: Arc<Engine>) -> *mut Self {
+    pub fn leaked(
+        engine: Arc<Engine>,
+        store: ConfigStore,
+        grammar_cache: GrammarCache,
+    ) -> *mut Self {
         let instance = Self {
             engine,
+            config: store.snapshot(),
+            store,
+            profile: None,
             speech_detector: Detector8kHz::new(false, 200, 1000, 5000, 20000),
             speech_detector_event: SpeechDetectorEvent::None,
+            speech_enabled: true,
+            builtins: vec![],
+            dtmf: DtmfCollector::default(),
+            tone_detector: ToneDetector::default(),
             data_channel: mpsc::channel(),
+            backend_task: None,
+            deferred: None,
+            last_result: None,
+            recording: None,
+            source: AudioSource::Live,
+            grammar_cache,
+            session_grammars: HashMap::new(),
+            grammars: GrammarState::Ready(Loaded::default()),
+            interpretation: None,
+            wakeup: Wakeup::default(),
+            stop_deadline: None,
+            hints: SpeechContext::default(),
+            route: None,
+            session_params: VendorParams::default(),
+            params: VendorParams::default(),
         };
         Box::into_raw(Box::new(instance))
     }
@@ -30,12 +219,38 @@ impl RecogBuffer {
         drop(Box::from_raw(this));
     }
 
-    pub fn prepare(&mut self, headers: RecogHeaders) {
-        self.speech_detector_event = SpeechDetectorEvent::None;
+    /// Arms the buffer for a RECOGNIZE with `builtins` and, if `srgs`,
+    /// SRGS grammars about to load. Speech is listened for unless all
+    /// grammars are DTMF ones.
+    pub fn prepare(
+        &mut self,
+        headers: RecogHeaders,
+        builtins: Vec<BuiltinGrammar>,
+        srgs: bool,
+        dtmf_params: DtmfParams,
+    ) {
+        self.cancel();
+        self.refresh_config();
+        self.last_result = None;
+        self.source = AudioSource::Live;
+        self.grammars = GrammarState::Ready(Loaded::default());
+        self.hints = SpeechContext::default();
+        self.speech_enabled = srgs
+            || builtins.is_empty()
+            || builtins.iter().any(|grammar| grammar.mode == Mode::Speech);
+        let dtmf_grammars = builtins
+            .iter()
+            .filter(|grammar| grammar.mode == Mode::Dtmf)
+            .cloned()
+            .map(DtmfGrammar::Builtin)
+            .collect();
+        self.dtmf.prepare(dtmf_grammars, dtmf_params, srgs);
+        self.builtins = builtins;
+        self.tone_detector.reset();
         let sensitivity = headers.sensitivity();
         self.speech_detector = Detector8kHz::new(
             headers.start_input_timers(),
-            100,
+            self.config.detector.speech_timeout,
             headers.silence_timeout(),
             headers.noinput_timeout(),
             headers.recognition_timeout(),
@@ -43,6 +258,23 @@ impl RecogBuffer {
         self.speech_detector.set_mode(sensitivity);
     }
 
+    /// Serves the channel with the profile its attributes select, if any.
+    pub fn select_profile(&mut self, attribs: &HashMap<String, String>) {
+        let config = self.store.snapshot();
+        self.profile = config.select_profile(attribs).map(str::to_owned);
+        log::info!("Profile {:?} of attributes {:?}", self.profile, attribs);
+        self.config = config.profiled(self.profile.as_deref());
+    }
+
+    /// Takes the current engine config for the request that starts.
+    pub fn refresh_config(&mut self) {
+        self.config = self.store.snapshot().profiled(self.profile.as_deref());
+    }
+
+    pub fn config(&self) -> &EngineConfig {
+        &self.config
+    }
+
     pub fn start_input_timers(&mut self) {
         self.speech_detector.timers_started = true;
     }
@@ -51,6 +283,290 @@ impl RecogBuffer {
         self.speech_detector.input_started = true;
     }
 
+    /// Takes the audio of the request from Input-Waveform-URI instead of
+    /// the live stream.
+    pub fn input_waveform(&mut self, uri: String, timeout: usize) {
+        let (tx, rx) = mpsc::channel();
+        let wakeup = self.wakeup.clone();
+        let policy = self.config.fetch.clone();
+        self.engine.async_handle().spawn(async move {
+            let pcm = fetch::fetch(&uri, timeout, &policy)
+                .await
+                .and_then(waveform::decode);
+            let _ = tx.send(pcm);
+            wakeup.wake();
+        });
+        self.source = AudioSource::Fetching(rx);
+    }
+
+    /// Keeps a grammar of DEFINE-GRAMMAR or an inline RECOGNIZE grammar for
+    /// the session.
+    pub fn define_grammar(&mut self, grammar: Grammar) {
+        log::info!("Define grammar {:?}", grammar.uri);
+        self.session_grammars
+            .insert(grammar.uri.clone(), Arc::new(grammar));
+    }
+
+    /// Resolves the grammars and `lexicons` of the request in the
+    /// background: session grammars directly, the others from the engine
+    /// cache or by fetching.
+    pub fn load_grammars(
+        &mut self,
+        uris: Vec<String>,
+        lexicons: Vec<String>,
+        timeout: usize,
+        control: CacheControl,
+    ) {
+        let mut session = HashMap::new();
+        for uri in uris.iter().filter(|uri| uri.starts_with("session:")) {
+            let Some(grammar) = self.session_grammars.get(uri) else {
+                let reason = format!("Unknown session grammar {:?}", uri);
+                self.grammars = GrammarState::Failed(LoadError::Fetch(reason));
+                return;
+            };
+            session.insert(uri.clone(), grammar.clone());
+        }
+        if session.len() == uris.len() {
+            let grammars = uris
+                .iter()
+                .filter_map(|uri| session.get(uri).cloned())
+                .collect::<Vec<_>>();
+            if loader::lexicon_uris(&grammars, &lexicons).is_empty() {
+                let lexicons = vec![];
+                self.grammars_ready(Loaded { grammars, lexicons });
+                return;
+            }
+        }
+        let rx = self.spawn_load(session, uris, lexicons, timeout, control);
+        self.grammars = GrammarState::Loading(rx);
+    }
+
+    /// Resolves the grammars and `lexicons` of an INTERPRET in the
+    /// background, fetching those neither defined nor cached.
+    pub fn load_interpretation(
+        &mut self,
+        uris: Vec<String>,
+        lexicons: Vec<String>,
+        timeout: usize,
+        control: CacheControl,
+    ) {
+        let session = uris
+            .iter()
+            .filter_map(|uri| Some((uri.clone(), self.session_grammars.get(uri)?.clone())))
+            .collect();
+        let rx = self.spawn_load(session, uris, lexicons, timeout, control);
+        self.interpretation = Some(rx);
+    }
+
+    /// Fails the INTERPRET in progress without loading its grammars.
+    pub fn interpretation_failed(&mut self, error: LoadError) {
+        let (tx, rx) = mpsc::channel();
+        let _ = tx.send(Err(error));
+        self.interpretation = Some(rx);
+        self.wakeup.wake();
+    }
+
+    /// Grammars of the INTERPRET in progress, once loaded.
+    pub fn interpretation(&mut self) -> Option<Result<Loaded, LoadError>> {
+        let loaded = match self.interpretation.as_ref()?.try_recv() {
+            Ok(loaded) => loaded,
+            Err(mpsc::TryRecvError::Empty) => return None,
+            Err(mpsc::TryRecvError::Disconnected) => {
+                Err(LoadError::Fetch(String::from("Grammar loader is gone")))
+            }
+        };
+        self.interpretation = None;
+        Some(loaded)
+    }
+
+    fn spawn_load(
+        &self,
+        session: HashMap<String, Arc<Grammar>>,
+        uris: Vec<String>,
+        lexicons: Vec<String>,
+        timeout: usize,
+        control: CacheControl,
+    ) -> mpsc::Receiver<Result<Loaded, LoadError>> {
+        let (tx, rx) = mpsc::channel();
+        let cache = self.grammar_cache.clone();
+        let wakeup = self.wakeup.clone();
+        let fetch_params = loader::FetchParams {
+            timeout,
+            policy: self.config.fetch.clone(),
+        };
+        self.engine.async_handle().spawn(async move {
+            let loaded = loader::load(cache, session, uris, lexicons, fetch_params, control).await;
+            let _ = tx.send(loaded);
+            wakeup.wake();
+        });
+        rx
+    }
+
+    pub fn wakeup(&self) -> &Wakeup {
+        &self.wakeup
+    }
+
+    /// Keeps vendor-specific parameters of SET-PARAMS for the next requests.
+    pub fn set_params(&mut self, params: VendorParams) {
+        self.session_params = self.session_params.merged(&params);
+    }
+
+    /// Tunes the detector and the backend with the session parameters
+    /// overridden by those of the request.
+    pub fn tune(&mut self, params: VendorParams) {
+        self.params = self.session_params.merged(&params);
+        let detector = &self.config.detector;
+        if let Some(threshold) = self.params.energy_threshold.or(detector.energy_threshold) {
+            self.speech_detector.set_energy_threshold(threshold);
+        }
+        let pre_roll = self.params.pre_roll.unwrap_or(detector.pre_roll);
+        self.speech_detector.set_pre_roll(pre_roll);
+    }
+
+    /// Picks the backend of the Speech-Language of the request.
+    pub fn route(&mut self, language: Option<String>) {
+        self.route = self.config.languages.route(language.as_deref());
+        match &self.route {
+            Some(route) => log::info!("Route {:?} to {:?}", language, route),
+            None => log::error!("No backend supports language {:?}", language),
+        }
+    }
+
+    /// Phrases of the request to favour on top of those of its grammars.
+    pub fn hint(&mut self, hints: SpeechContext) {
+        self.hints = hints;
+    }
+
+    fn speech_context(&self) -> SpeechContext {
+        let grammar_phrases = self
+            .grammars()
+            .iter()
+            .filter(|grammar| grammar.mode == crate::builtin::Mode::Speech)
+            .flat_map(|grammar| grammar.phrases());
+        let spellings = self
+            .lexicons()
+            .iter()
+            .flat_map(|lexicon| lexicon.spellings());
+        let mut seen = HashSet::new();
+        let phrases = self
+            .hints
+            .phrases
+            .iter()
+            .map(String::as_str)
+            .chain(spellings)
+            .chain(grammar_phrases)
+            .filter(|phrase| !phrase.is_empty() && seen.insert(*phrase))
+            .take(self.config.limits.max_hints)
+            .map(str::to_owned)
+            .collect();
+        let pronunciations = self
+            .lexicons()
+            .iter()
+            .flat_map(|lexicon| lexicon.pronunciations().iter().cloned())
+            .collect();
+        SpeechContext {
+            phrases,
+            boost: self.hints.boost,
+            pronunciations,
+        }
+    }
+
+    /// Hands the DTMF grammars among `loaded` to the collector, and stops
+    /// listening for speech if no grammar takes it.
+    fn grammars_ready(&mut self, loaded: Loaded) {
+        let speech = self
+            .builtins
+            .iter()
+            .map(|grammar| grammar.mode)
+            .chain(loaded.grammars.iter().map(|grammar| grammar.mode))
+            .collect::<Vec<_>>();
+        if !speech.is_empty() && !speech.contains(&Mode::Speech) {
+            self.speech_enabled = false;
+            self.cancel();
+        }
+        let dtmf_grammars = loaded
+            .grammars
+            .iter()
+            .filter(|grammar| grammar.mode == Mode::Dtmf)
+            .cloned()
+            .map(DtmfGrammar::Srgs)
+            .collect();
+        self.dtmf.add_grammars(dtmf_grammars);
+        self.grammars = GrammarState::Ready(loaded);
+        if let Some(audio) = self.deferred.take() {
+            self.send(audio);
+        }
+    }
+
+    pub fn grammars_failed(&mut self, error: LoadError) {
+        self.grammars = GrammarState::Failed(error);
+    }
+
+    pub fn grammars_loading(&self) -> bool {
+        matches!(self.grammars, GrammarState::Loading(_))
+    }
+
+    pub fn grammars(&self) -> &[Arc<Grammar>] {
+        match &self.grammars {
+            GrammarState::Ready(loaded) => &loaded.grammars,
+            _ => &[],
+        }
+    }
+
+    pub fn lexicons(&self) -> &[Arc<Lexicon>] {
+        match &self.grammars {
+            GrammarState::Ready(loaded) => &loaded.lexicons,
+            _ => &[],
+        }
+    }
+
+    /// Polls the background work of the request and reports whether it failed.
+    pub fn failure(&mut self) -> Option<Failure> {
+        if self.input_waveform_failed() {
+            return Some(Failure::InputWaveform);
+        }
+        if self.route.is_none() {
+            return Some(Failure::LanguageUnsupported);
+        }
+        if let GrammarState::Loading(rx) = &self.grammars {
+            match rx.try_recv() {
+                Ok(Ok(loaded)) => self.grammars_ready(loaded),
+                Ok(Err(e)) => {
+                    log::error!("Failed to load grammars. {:?}", e);
+                    self.grammars = GrammarState::Failed(e);
+                }
+                Err(mpsc::TryRecvError::Empty) => return None,
+                Err(mpsc::TryRecvError::Disconnected) => {
+                    let reason = String::from("Grammar loader is gone");
+                    self.grammars = GrammarState::Failed(LoadError::Fetch(reason));
+                }
+            }
+        }
+        match &self.grammars {
+            GrammarState::Failed(LoadError::Fetch(_)) => Some(Failure::GrammarLoad),
+            GrammarState::Failed(LoadError::Compile(_)) => Some(Failure::GrammarCompile),
+            _ => None,
+        }
+    }
+
+    fn input_waveform_failed(&mut self) -> bool {
+        if let AudioSource::Fetching(rx) = &self.source {
+            self.source = match rx.try_recv() {
+                Ok(Ok(pcm)) => {
+                    log::info!("Loaded input waveform of {} bytes", pcm.len());
+                    AudioSource::Waveform { pcm, position: 0 }
+                }
+                Ok(Err(e)) => {
+                    log::error!("{}", e);
+                    AudioSource::Failed
+                }
+                Err(mpsc::TryRecvError::Empty) => return false,
+                Err(mpsc::TryRecvError::Disconnected) => AudioSource::Failed,
+            };
+        }
+        matches!(self.source, AudioSource::Failed)
+    }
+
     pub fn input_started(&self) -> bool {
         if self.speech_detector.timers_started {
             self.speech_detector.input_started
@@ -61,12 +577,11 @@ impl RecogBuffer {
 }
 
 impl Write for RecogBuffer {
+    /// Processes a live frame, unless the request takes its audio from
+    /// Input-Waveform-URI.
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
-        let next_event = self
-            .speech_detector
-            .process(buf, rsunimrcp_sys::uni::CODEC_FRAME_TIME_BASE as _);
-        if self.speech_detector_event != SpeechDetectorEvent::Recognizing {
-            self.speech_detector_event = next_event;
+        if matches!(self.source, AudioSource::Live) {
+            self.process(buf);
         }
         Ok(buf.len())
     }
@@ -77,10 +592,120 @@ impl Write for RecogBuffer {
 }
 
 impl RecogBuffer {
+    pub fn live(&self) -> bool {
+        matches!(self.source, AudioSource::Live)
+    }
+
+    /// Processes the next frame of the Input-Waveform-URI audio, silence
+    /// once it ends. Returns false if the waveform is not loaded.
+    pub fn pump_waveform(&mut self) -> bool {
+        let AudioSource::Waveform { pcm, position } = &mut self.source else {
+            return false;
+        };
+        let start = (*position).min(pcm.len());
+        let end = (start + FRAME_BYTES).min(pcm.len());
+        let mut frame = pcm[start..end].to_vec();
+        frame.resize(FRAME_BYTES, 0);
+        *position = end;
+        self.process(&frame);
+        true
+    }
+
+    fn process(&mut self, buf: &[u8]) {
+        let detection = if self.config.inband_dtmf {
+            self.tone_detector.process(buf)
+        } else {
+            Default::default()
+        };
+        if let Some(key) = detection.key {
+            log::info!("Detected in-band DTMF {:?}", key);
+            // The tone was already heard before the detector confirmed it.
+            let speech = &mut self.speech_detector.speech;
+            let onset = speech.len().saturating_sub(tone_detector::ONSET_BYTES);
+            speech[onset..].fill(0);
+        }
+        let silence;
+        let buf = if detection.tone {
+            silence = vec![0; buf.len()];
+            &silence
+        } else {
+            buf
+        };
+        let next_event = self
+            .speech_detector
+            .process(buf, rsunimrcp_sys::uni::CODEC_FRAME_TIME_BASE as _);
+        if self.speech_detector_event != SpeechDetectorEvent::Recognizing {
+            self.speech_detector_event = next_event;
+        }
+        self.dtmf.frame(
+            detection.key,
+            rsunimrcp_sys::uni::CODEC_FRAME_TIME_BASE as _,
+        );
+    }
+
     pub fn detector_event(&self) -> SpeechDetectorEvent {
         self.speech_detector_event
     }
 
+    pub fn speech_enabled(&self) -> bool {
+        self.speech_enabled
+    }
+
+    pub fn dtmf_key(&mut self, key: char) {
+        self.dtmf.key(key);
+    }
+
+    pub fn dtmf_tick(&mut self) {
+        self.dtmf
+            .tick(rsunimrcp_sys::uni::CODEC_FRAME_TIME_BASE as _);
+    }
+
+    pub fn dtmf_event(&self) -> DtmfEvent {
+        self.dtmf.event()
+    }
+
+    pub fn dtmf_digits(&self) -> &str {
+        self.dtmf.digits()
+    }
+
+    pub fn dtmf_finish(&mut self) -> RecogResult {
+        self.dtmf.finish()
+    }
+
+    /// Keeps the unfiltered result of the completed RECOGNIZE for GET-RESULT.
+    pub fn keep_result(&mut self, result: RecogResult) {
+        self.last_result = Some(result);
+    }
+
+    pub fn last_result(&self) -> Option<&RecogResult> {
+        self.last_result.as_ref()
+    }
+
+    /// Drops the speech collected so far in favour of DTMF input.
+    pub fn switch_to_dtmf(&mut self) {
+        self.speech_enabled = false;
+        self.cancel();
+    }
+
+    pub fn complete(&mut self) {
+        self.interpretation = None;
+        self.dtmf.stop();
+        self.cancel();
+    }
+
+    /// Aborts the backend request in flight. A fresh result channel makes
+    /// sure a late answer of the aborted task is never loaded.
+    fn cancel(&mut self) {
+        if let Some(task) = self.backend_task.take() {
+            task.abort();
+        }
+        self.data_channel = mpsc::channel();
+        self.deferred = None;
+        self.stop_deadline = None;
+        self.speech_detector.speech.clear();
+        self.speech_detector_event = SpeechDetectorEvent::None;
+    }
+
     pub fn duration_timeout(&self) -> usize {
         self.speech_detector.duration_timeout
     }
@@ -100,48 +725,154 @@ impl RecogBuffer {
         self.speech_detector_event = SpeechDetectorEvent::None;
     }
 
-    pub fn load_result(&self) -> Option<String> {
+    /// Listens for the next dictation segment.
+    pub fn next_segment(&mut self) {
+        self.speech_detector.rearm();
+        self.restart_writing();
+    }
+
+    /// Appends a dictation segment to the configured sink file.
+    pub fn sink_segment(&self, channel_id: String, text: String) {
+        let Some(path) = self.config.dictation_sink.clone() else {
+            return;
+        };
+        self.engine.async_handle().spawn(async move {
+            let line = format!("{}\t{}\n", channel_id, text);
+            let file = tokio::fs::OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .await;
+            let written = match file {
+                Ok(mut file) => file.write_all(line.as_bytes()).await,
+                Err(e) => Err(e),
+            };
+            if let Err(e) = written {
+                log::error!("Failed to append segment to {:?}. {:?}", path, e);
+            }
+        });
+    }
+
+    /// Collects the audio sent to the backend for Save-Waveform. Only the
+    /// latest utterance is kept.
+    pub fn record(&mut self, enabled: bool) {
+        self.recording = enabled.then(Vec::new);
+    }
+
+    /// Writes the recorded utterance in the background and returns the
+    /// Waveform-URI value, if anything was recorded.
+    pub fn save_waveform(&mut self, waveform: Waveform) -> Option<String> {
+        let pcm = self.recording.take().filter(|pcm| !pcm.is_empty())?;
+        let header = waveform.header(&pcm);
+        self.engine.async_handle().spawn(async move {
+            if let Some(dir) = waveform.path.parent() {
+                if let Err(e) = tokio::fs::create_dir_all(dir).await {
+                    log::error!("Failed to create {:?}. {:?}", dir, e);
+                }
+            }
+            let data = waveform.media_type.encode(&pcm);
+            if let Err(e) = tokio::fs::write(&waveform.path, data).await {
+                log::error!("Failed to save waveform {:?}. {:?}", waveform.path, e);
+            }
+        });
+        Some(header)
+    }
+
+    pub fn load_result(&self) -> Option<RecogResult> {
         let rx = &self.data_channel.1;
         match rx.try_recv() {
             Ok(result) => Some(result),
             Err(mpsc::TryRecvError::Empty) => None,
             Err(mpsc::TryRecvError::Disconnected) => {
                 log::error!("Unable to load results from STT.");
-                Some(String::new())
+                Some(RecogResult::default())
+            }
+        }
+    }
+
+    /// Sends the utterance collected so far to the backend, unless a result
+    /// is already on its way, and gives it `timeout` milliseconds to answer.
+    /// Returns false if there is nothing to recognize.
+    pub fn recognize_partial(&mut self, timeout: u64) -> bool {
+        if self.speech_detector_event != SpeechDetectorEvent::Recognizing {
+            if !self.speech_enabled || self.speech_detector.speech.is_empty() {
+                return false;
             }
+            self.recognize(0);
         }
+        self.dtmf.stop();
+        let timeout = Duration::from_millis(timeout);
+        self.stop_deadline = Some(Instant::now() + timeout);
+        let wakeup = self.wakeup.clone();
+        self.engine.async_handle().spawn(async move {
+            tokio::time::sleep(timeout).await;
+            wakeup.wake();
+        });
+        true
+    }
+
+    /// Whether the backend missed the deadline of the partial utterance.
+    pub fn stop_expired(&self) -> bool {
+        self.stop_deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
     }
 
+    /// Sends the utterance to the backend, once the grammars, whose phrases
+    /// go along as hints, are loaded.
     pub fn recognize(&mut self, duration: usize) {
         let data = std::mem::take(&mut self.speech_detector.speech);
         self.decrease_noinput(duration);
         self.speech_detector_event = SpeechDetectorEvent::Recognizing;
-        log::info!("Send {} bytes to STT.", data.len());
+        if let Some(recording) = self.recording.as_mut() {
+            recording.clone_from(&data);
+        }
+        if self.grammars_loading() {
+            log::info!("Hold {} bytes until grammars are loaded", data.len());
+            self.deferred = Some(data);
+        } else {
+            self.send(data);
+        }
+    }
+
+    fn send(&mut self, audio: Vec<u8>) {
+        let request = BackendRequest {
+            audio,
+            context: self.speech_context(),
+            route: self.route.clone().unwrap_or_default(),
+            params: self.params.clone(),
+            filename: self.engine.filename().to_owned(),
+        };
+        log::info!(
+            "Send {} bytes with {} hints to STT {:?} in {:?}.",
+            request.audio.len(),
+            request.context.phrases.len(),
+            request.route.backend,
+            request.route.language
+        );
         let tx = self.data_channel.0.clone();
-        self.engine
-            .async_handle()
-            .spawn(connect(data, self.engine.filename().to_owned(), tx));
+        let wakeup = self.wakeup.clone();
+        let task = connect(request, tx, wakeup);
+        self.backend_task = Some(self.engine.async_handle().spawn(task));
     }
 }
 
-async fn connect(data: Vec<u8>, filename: String, tx: mpsc::Sender<String>) {
-    if data.is_empty() {
-        tx.send(String::new()).unwrap();
+async fn connect(request: BackendRequest, tx: mpsc::Sender<RecogResult>, wakeup: Wakeup) {
+    if request.audio.is_empty() {
+        let _ = tx.send(RecogResult::default());
+        wakeup.wake();
         return;
     }
-    let seconds = data.len() / 16000;
-    let Ok(mut output) = tokio::fs::File::create(&filename).await else {
-        log::error!("Failed to create {:?}", filename);
-        tx.send(String::new()).unwrap();
-        return;
-    };
-    match output.write_all(&data).await {
-        Ok(_) => {
-            let text = format!("Recognized {} seconds.", seconds);
-            let _ = tx.send(text);
+    if !request.filename.is_empty() {
+        if let Err(e) = tokio::fs::write(&request.filename, &request.audio).await {
+            log::error!("Failed to write into {:?}. {:?}", request.filename, e);
         }
-        Err(e) => {
-            log::error!("Failed to write into {:?}. {:?}", filename, e);
+        let path = format!("{}.request", request.filename);
+        if let Err(e) = tokio::fs::write(&path, request.settings()).await {
+            log::error!("Failed to write into {:?}. {:?}", path, e);
         }
     }
+    let seconds = request.audio.len() / 16000;
+    let text = format!("Recognized {} seconds.", seconds);
+    let _ = tx.send(RecogResult::new(vec![Alternative::new(text, 1.0)]));
+    wakeup.wake();
 }
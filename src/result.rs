@@ -0,0 +1,130 @@
+use crate::nlsml::InputMode;
+
+pub const DEFAULT_CONFIDENCE_THRESHOLD: f32 = 0.0;
+pub const DEFAULT_N_BEST_LIST_LENGTH: usize = 1;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alternative {
+    pub text: String,
+    pub confidence: f32,
+    pub instance: Option<String>,
+    pub grammar: Option<String>,
+    /// Weight of the matched grammar, ranking the hypotheses along with
+    /// the confidence.
+    pub weight: f32,
+}
+
+impl Alternative {
+    pub fn new(text: impl Into<String>, confidence: f32) -> Self {
+        Self {
+            text: text.into(),
+            confidence: confidence.clamp(0.0, 1.0),
+            instance: None,
+            grammar: None,
+            weight: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RecogResult {
+    pub mode: InputMode,
+    pub alternatives: Vec<Alternative>,
+}
+
+impl RecogResult {
+    pub fn new(alternatives: Vec<Alternative>) -> Self {
+        Self {
+            mode: InputMode::Speech,
+            alternatives,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.alternatives
+            .iter()
+            .all(|alternative| alternative.text.trim().is_empty())
+    }
+
+    /// Attributes the hypotheses no grammar has claimed to `grammar`.
+    pub fn attributed(mut self, grammar: Option<String>) -> Self {
+        for alternative in self.alternatives.iter_mut() {
+            if alternative.grammar.is_none() {
+                alternative.grammar.clone_from(&grammar);
+            }
+        }
+        self
+    }
+
+    pub fn best(&self) -> Option<&Alternative> {
+        self.alternatives.first()
+    }
+
+    /// Orders the hypotheses by confidence times weight, drops those below
+    /// the threshold and keeps at most `n_best_list_length` of them.
+    pub fn filtered(&self, confidence_threshold: f32, n_best_list_length: usize) -> Self {
+        let mut alternatives = self
+            .alternatives
+            .iter()
+            .filter(|alternative| !alternative.text.trim().is_empty())
+            .filter(|alternative| alternative.confidence >= confidence_threshold)
+            .cloned()
+            .collect::<Vec<_>>();
+        let rank = |alternative: &Alternative| alternative.confidence * alternative.weight;
+        alternatives.sort_by(|a, b| {
+            rank(b)
+                .total_cmp(&rank(a))
+                .then(b.weight.total_cmp(&a.weight))
+        });
+        alternatives.truncate(n_best_list_length.max(1));
+        Self {
+            mode: self.mode,
+            alternatives,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn alternative(text: &str, confidence: f32, weight: f32) -> Alternative {
+        Alternative {
+            weight,
+            ..Alternative::new(text, confidence)
+        }
+    }
+
+    fn texts(result: &RecogResult) -> Vec<&str> {
+        result
+            .alternatives
+            .iter()
+            .map(|alternative| alternative.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn ranks_by_weighted_confidence() {
+        let result = RecogResult::new(vec![
+            alternative("light", 0.9, 0.5),
+            alternative("heavy", 0.6, 1.0),
+            alternative("tied", 0.3, 2.0),
+            alternative("quiet", 0.2, 1.0),
+        ]);
+        let filtered = result.filtered(0.0, 4);
+        assert_eq!(texts(&filtered), ["tied", "heavy", "light", "quiet"]);
+        assert_eq!(filtered.alternatives[2].confidence, 0.9);
+    }
+
+    #[test]
+    fn drops_empty_and_unconfident_hypotheses() {
+        let result = RecogResult::new(vec![
+            Alternative::new("yes", 0.8),
+            Alternative::new(" ", 0.9),
+            Alternative::new("no", 0.4),
+            Alternative::new("maybe", 0.6),
+        ]);
+        assert_eq!(texts(&result.filtered(0.5, 5)), ["yes", "maybe"]);
+        assert_eq!(texts(&result.filtered(0.0, 0)), ["yes"]);
+    }
+}
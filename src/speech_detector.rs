@@ -1,4 +1,6 @@
 const MAX_SENSITIVITY: usize = 1200;
+/// Bytes of 16-bit LPCM at 8 kHz per millisecond.
+const BYTES_PER_MS: usize = 16;
 
 #[derive(Debug, PartialEq, Clone, Copy)]
 pub enum SpeechDetectorEvent {
@@ -35,6 +37,10 @@ pub struct Detector8kHz {
     in_sensitivity: usize,
     out_sensitivity: usize,
     state: SpeechDetectorState,
+    /// Mean sample amplitude of a frame taken for activity, if any.
+    energy_threshold: Option<usize>,
+    pre_roll: usize,
+    recent: Vec<u8>,
 }
 
 impl Detector8kHz {
@@ -59,6 +65,9 @@ impl Detector8kHz {
             in_sensitivity: 32,
             out_sensitivity: 512,
             state: SpeechDetectorState::Inactivity,
+            energy_threshold: None,
+            pre_roll: 0,
+            recent: vec![],
         }
     }
 
@@ -77,21 +86,44 @@ impl Detector8kHz {
         };
     }
 
+    /// Frame energy taken for activity. Quieter frames keep the detector
+    /// inactive, and end the activity once they last the silence timeout.
+    pub fn set_energy_threshold(&mut self, threshold: usize) {
+        self.energy_threshold = Some(threshold);
+    }
+
+    /// Keeps `duration` milliseconds of audio before the activity.
+    pub fn set_pre_roll(&mut self, duration: usize) {
+        self.pre_roll = duration.saturating_mul(BYTES_PER_MS);
+    }
+
     pub fn process(&mut self, frame: &[u8], duration: usize) -> SpeechDetectorEvent {
         let mut result = SpeechDetectorEvent::None;
         match self.state {
             SpeechDetectorState::Inactivity => {
-                self.change_state(SpeechDetectorState::ActivityTransition);
+                self.keep_recent(frame);
+                if !self.quiet(frame) {
+                    self.change_state(SpeechDetectorState::ActivityTransition);
+                }
             }
             SpeechDetectorState::ActivityTransition => {
+                self.speech.append(&mut self.recent);
                 self.activity_duration = duration;
+                self.inactivity_duration = 0;
                 self.change_state(SpeechDetectorState::Activity);
                 result = SpeechDetectorEvent::Activity;
             }
             SpeechDetectorState::Activity => {
                 self.activity_duration += duration;
                 self.speech.extend_from_slice(frame);
-                if self.speech.len() > 16000 {
+                if self.quiet(frame) {
+                    self.inactivity_duration += duration;
+                } else {
+                    self.inactivity_duration = 0;
+                }
+                let silent = self.energy_threshold.is_some()
+                    && self.inactivity_duration >= self.silence_timeout;
+                if silent || self.speech.len() > 16000 {
                     self.change_state(SpeechDetectorState::InactivityTransition);
                 }
             }
@@ -114,7 +146,140 @@ impl Detector8kHz {
 }
 
 impl Detector8kHz {
+    /// Starts counting the recognition timeout anew for the next segment.
+    pub fn rearm(&mut self) {
+        self.total_duration = 0;
+        if matches!(self.state, SpeechDetectorState::Exhausted) {
+            self.change_state(SpeechDetectorState::Inactivity);
+        }
+    }
+
+    /// Whether `frame` is below the energy threshold, if there is one.
+    fn quiet(&self, frame: &[u8]) -> bool {
+        self.energy_threshold
+            .is_some_and(|threshold| energy(frame) < threshold)
+    }
+
+    fn keep_recent(&mut self, frame: &[u8]) {
+        if self.pre_roll == 0 {
+            return;
+        }
+        self.recent.extend_from_slice(frame);
+        let excess = self.recent.len().saturating_sub(self.pre_roll);
+        self.recent.drain(..excess);
+    }
+
     fn change_state(&mut self, state: SpeechDetectorState) {
         self.state = state;
     }
 }
+
+/// Mean amplitude of the 16-bit LPCM samples of `frame`.
+fn energy(frame: &[u8]) -> usize {
+    let samples = frame.chunks_exact(2);
+    let count = samples.len();
+    if count == 0 {
+        return 0;
+    }
+    let total: usize = samples
+        .map(|sample| i16::from_le_bytes([sample[0], sample[1]]).unsigned_abs() as usize)
+        .sum();
+    total / count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 10 ms frame of samples of `amplitude`.
+    fn frame(amplitude: i16) -> Vec<u8> {
+        amplitude.to_le_bytes().repeat(80)
+    }
+
+    fn detector(threshold: Option<usize>) -> Detector8kHz {
+        let mut detector = Detector8kHz::new(true, 100, 50, 5000, 20000);
+        if let Some(threshold) = threshold {
+            detector.set_energy_threshold(threshold);
+        }
+        detector
+    }
+
+    /// Events of `frames` that are not None.
+    fn events(detector: &mut Detector8kHz, frames: &[Vec<u8>]) -> Vec<SpeechDetectorEvent> {
+        frames
+            .iter()
+            .map(|frame| detector.process(frame, 10))
+            .filter(|event| *event != SpeechDetectorEvent::None)
+            .collect()
+    }
+
+    #[test]
+    fn quiet_frames_keep_inactive() {
+        let mut detector = detector(Some(100));
+        assert!(events(&mut detector, &vec![frame(-99); 50]).is_empty());
+        let loud = [frame(-100), frame(0)];
+        assert_eq!(
+            events(&mut detector, &loud),
+            [SpeechDetectorEvent::Activity]
+        );
+    }
+
+    #[test]
+    fn quiet_tail_ends_activity() {
+        let mut detector = detector(Some(100));
+        let mut frames = vec![frame(1000); 10];
+        frames.extend(vec![frame(10); 6]);
+        assert_eq!(
+            events(&mut detector, &frames),
+            [
+                SpeechDetectorEvent::Activity,
+                SpeechDetectorEvent::Inactivity { duration: 140 }
+            ]
+        );
+    }
+
+    #[test]
+    fn loud_frames_reset_the_silence() {
+        let mut detector = detector(Some(100));
+        let mut frames = vec![frame(1000); 2];
+        for _ in 0..10 {
+            frames.extend([frame(10), frame(10), frame(1000)]);
+        }
+        assert_eq!(
+            events(&mut detector, &frames),
+            [SpeechDetectorEvent::Activity]
+        );
+    }
+
+    #[test]
+    fn ends_activity_by_length_without_threshold() {
+        let mut detector = detector(None);
+        let events = events(&mut detector, &vec![frame(0); 104]);
+        assert_eq!(
+            events,
+            [
+                SpeechDetectorEvent::Activity,
+                SpeechDetectorEvent::Inactivity { duration: 1020 }
+            ]
+        );
+        assert_eq!(detector.speech.len(), 102 * 160);
+    }
+
+    #[test]
+    fn keeps_pre_roll_before_activity() {
+        let mut detector = detector(Some(100));
+        detector.set_pre_roll(20);
+        for amplitude in [1, 2, 3, 4, 1000, 1000] {
+            detector.process(&frame(amplitude), 10);
+        }
+        let samples: Vec<i16> = detector
+            .speech
+            .chunks_exact(160)
+            .map(|frame| i16::from_le_bytes([frame[0], frame[1]]))
+            .collect();
+        assert_eq!(samples, [4, 1000]);
+
+        detector.set_pre_roll(usize::MAX);
+        assert_eq!(detector.pre_roll, usize::MAX);
+    }
+}
@@ -0,0 +1,407 @@
+use crate::builtin::Mode;
+use crate::fetch;
+use crate::fuzzy::FuzzyMatcher;
+use roxmltree::Node;
+use std::{collections::HashMap, ops::Range};
+
+/// Phrases a grammar may expand to before it is rejected.
+const MAX_PHRASES: usize = 10000;
+const MAX_RULE_DEPTH: usize = 16;
+/// Repetitions allowed beyond the minimum of an open `repeat="m-"`.
+const OPEN_REPEAT: usize = 3;
+/// Repetitions an item may ask for at most.
+const MAX_REPEAT: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq)]
+struct Expansion {
+    words: Vec<String>,
+    tag: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Phrase {
+    text: String,
+    instance: Option<String>,
+}
+
+impl Phrase {
+    fn instance(&self) -> String {
+        self.instance.clone().unwrap_or_else(|| self.text.clone())
+    }
+
+    /// Keys of a phrase of a DTMF grammar, like `123#` for `1 2 3 #`.
+    fn keys(&self) -> String {
+        self.text.split(' ').collect()
+    }
+}
+
+/// SRGS XML grammar compiled into the phrases it accepts.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Grammar {
+    pub uri: String,
+    pub mode: Mode,
+    /// URIs of the PLS lexicons the grammar references.
+    pub lexicons: Vec<String>,
+    phrases: Vec<Phrase>,
+}
+
+impl Grammar {
+    pub fn compile(uri: &str, source: &str) -> Result<Self, String> {
+        if !source.trim_start().starts_with('<') {
+            return Err(format!("Grammar {:?} is not SRGS XML", uri));
+        }
+        let document = roxmltree::Document::parse(source)
+            .map_err(|e| format!("Invalid SRGS in {:?}. {}", uri, e))?;
+        let root = document.root_element();
+        if root.tag_name().name() != "grammar" {
+            return Err(format!("No <grammar> in {:?}", uri));
+        }
+        let rules = root
+            .children()
+            .filter(|node| node.tag_name().name() == "rule")
+            .filter_map(|rule| rule.attribute("id").map(|id| (id, rule)))
+            .collect::<HashMap<_, _>>();
+        let root_rule = match root.attribute("root") {
+            Some(id) => id,
+            None => root
+                .children()
+                .find(|node| node.tag_name().name() == "rule")
+                .and_then(|rule| rule.attribute("id"))
+                .ok_or_else(|| format!("No rules in {:?}", uri))?,
+        };
+        let mode = match root.attribute("mode") {
+            Some("dtmf") => Mode::Dtmf,
+            _ => Mode::Speech,
+        };
+        let compiler = Compiler { rules, mode };
+        let phrases = compiler
+            .rule(root_rule, 0)?
+            .into_iter()
+            .map(|expansion| Phrase {
+                text: expansion.words.join(" "),
+                instance: expansion.tag,
+            })
+            .collect();
+        let lexicons = root
+            .children()
+            .filter(|node| node.tag_name().name() == "lexicon")
+            .filter_map(|lexicon| lexicon.attribute("uri"))
+            .map(|lexicon| fetch::resolve(uri, lexicon))
+            .collect();
+        Ok(Self {
+            uri: uri.to_owned(),
+            mode,
+            lexicons,
+            phrases,
+        })
+    }
+
+    /// Literal phrases the grammar accepts.
+    pub fn phrases(&self) -> impl Iterator<Item = &str> {
+        self.phrases.iter().map(|phrase| phrase.text.as_str())
+    }
+
+    /// Instance of the phrase `input` matches: the tag value if the phrase
+    /// carries one, otherwise the phrase itself.
+    pub fn interpret(&self, input: &str) -> Option<String> {
+        let input = normalize(input);
+        self.phrases
+            .iter()
+            .find(|phrase| phrase.text == input)
+            .map(Phrase::instance)
+    }
+
+    /// Most keys of the phrases of a DTMF grammar.
+    pub fn max_keys(&self) -> usize {
+        self.phrases
+            .iter()
+            .map(|phrase| phrase.keys().len())
+            .max()
+            .unwrap_or_default()
+    }
+
+    /// Instance of the phrase of a DTMF grammar the `keys` spell.
+    pub fn interpret_keys(&self, keys: &str) -> Option<String> {
+        self.phrases
+            .iter()
+            .find(|phrase| phrase.keys() == keys)
+            .map(Phrase::instance)
+    }
+
+    /// Like `interpret`, but falls back to the phrase closest to `input`.
+    /// Returns the similarity of the phrase as well.
+    pub fn interpret_fuzzy(&self, input: &str, matcher: &FuzzyMatcher) -> Option<(String, f32)> {
+        if let Some(instance) = self.interpret(input) {
+            return Some((instance, 1.0));
+        }
+        let (index, similarity) = matcher.closest(input, self.phrases())?;
+        Some((self.phrases[index].instance(), similarity))
+    }
+}
+
+struct Compiler<'a, 'input> {
+    rules: HashMap<&'a str, Node<'a, 'input>>,
+    mode: Mode,
+}
+
+impl Compiler<'_, '_> {
+    fn rule(&self, id: &str, depth: usize) -> Result<Vec<Expansion>, String> {
+        if depth > MAX_RULE_DEPTH {
+            return Err(format!("Rule {:?} nests too deep", id));
+        }
+        let rule = self
+            .rules
+            .get(id)
+            .ok_or_else(|| format!("Unknown rule {:?}", id))?;
+        self.sequence(*rule, depth)
+    }
+
+    fn sequence(&self, node: Node, depth: usize) -> Result<Vec<Expansion>, String> {
+        let mut expansions = vec![Expansion::default()];
+        for child in node.children() {
+            let part = if child.is_text() {
+                vec![Expansion {
+                    words: self.words(child.text().unwrap_or_default()),
+                    tag: None,
+                }]
+            } else if child.is_element() {
+                match child.tag_name().name() {
+                    "item" => self.item(child, depth)?,
+                    "one-of" => self.one_of(child, depth)?,
+                    "ruleref" => self.ruleref(child, depth)?,
+                    "token" => vec![Expansion {
+                        words: self.words(&child.text().unwrap_or_default().replace('"', "")),
+                        tag: None,
+                    }],
+                    "tag" => vec![Expansion {
+                        words: vec![],
+                        tag: Some(tag_value(child.text().unwrap_or_default())),
+                    }],
+                    _ => continue,
+                }
+            } else {
+                continue;
+            };
+            expansions = product(&expansions, &part)?;
+        }
+        Ok(expansions)
+    }
+
+    /// Words of `text`. DTMF keys keep their case, `A` to `D` included.
+    fn words(&self, text: &str) -> Vec<String> {
+        match self.mode {
+            Mode::Speech => words(text),
+            Mode::Dtmf => word_spans(text)
+                .into_iter()
+                .map(|span| text[span].to_owned())
+                .collect(),
+        }
+    }
+
+    fn item(&self, node: Node, depth: usize) -> Result<Vec<Expansion>, String> {
+        let body = self.sequence(node, depth)?;
+        let Some(repeat) = node.attribute("repeat") else {
+            return Ok(body);
+        };
+        let (min, max) = repeat_range(repeat)
+            .filter(|(_, max)| *max <= MAX_REPEAT)
+            .ok_or_else(|| format!("Invalid repeat {:?}", repeat))?;
+        if body.is_empty() {
+            return Ok(if min == 0 {
+                vec![Expansion::default()]
+            } else {
+                vec![]
+            });
+        }
+        let mut expansions = vec![];
+        let mut repeated = vec![Expansion::default()];
+        for count in 0..=max {
+            if count >= min {
+                expansions.extend(repeated.iter().cloned());
+            }
+            if count < max {
+                repeated = product(&repeated, &body)?;
+            }
+            if expansions.len() > MAX_PHRASES {
+                return Err(String::from("Grammar expands to too many phrases"));
+            }
+        }
+        Ok(expansions)
+    }
+
+    fn one_of(&self, node: Node, depth: usize) -> Result<Vec<Expansion>, String> {
+        let mut expansions = vec![];
+        for item in node
+            .children()
+            .filter(|child| child.tag_name().name() == "item")
+        {
+            expansions.extend(self.item(item, depth)?);
+            if expansions.len() > MAX_PHRASES {
+                return Err(String::from("Grammar expands to too many phrases"));
+            }
+        }
+        Ok(expansions)
+    }
+
+    fn ruleref(&self, node: Node, depth: usize) -> Result<Vec<Expansion>, String> {
+        match (node.attribute("uri"), node.attribute("special")) {
+            (Some(uri), _) => match uri.strip_prefix('#') {
+                Some(id) => self.rule(id, depth + 1),
+                None => Err(format!("External ruleref {:?} is not supported", uri)),
+            },
+            (None, Some("NULL" | "GARBAGE")) => Ok(vec![Expansion::default()]),
+            (None, Some("VOID")) => Ok(vec![]),
+            _ => Err(String::from("Invalid ruleref")),
+        }
+    }
+}
+
+fn product(left: &[Expansion], right: &[Expansion]) -> Result<Vec<Expansion>, String> {
+    if left.len() * right.len() > MAX_PHRASES {
+        return Err(String::from("Grammar expands to too many phrases"));
+    }
+    let mut expansions = Vec::with_capacity(left.len() * right.len());
+    for first in left {
+        for second in right {
+            let mut words = first.words.clone();
+            words.extend(second.words.iter().cloned());
+            expansions.push(Expansion {
+                words,
+                tag: second.tag.clone().or_else(|| first.tag.clone()),
+            });
+        }
+    }
+    Ok(expansions)
+}
+
+fn repeat_range(repeat: &str) -> Option<(usize, usize)> {
+    match repeat.split_once('-') {
+        Some((min, "")) => {
+            let min = min.trim().parse().ok()?;
+            Some((min, min.max(1) + OPEN_REPEAT))
+        }
+        Some((min, max)) => {
+            let (min, max) = (min.trim().parse().ok()?, max.trim().parse().ok()?);
+            (min <= max).then_some((min, max))
+        }
+        None => repeat.trim().parse().ok().map(|count| (count, count)),
+    }
+}
+
+/// Value of simple SISR tags like `out="yes";`, otherwise the tag text.
+fn tag_value(tag: &str) -> String {
+    let tag = tag.trim().trim_end_matches(';').trim();
+    let value = tag
+        .strip_prefix("out")
+        .map(str::trim_start)
+        .and_then(|value| value.strip_prefix('='))
+        .map(str::trim)
+        .unwrap_or(tag);
+    value.trim_matches(|c| c == '"' || c == '\'').to_owned()
+}
+
+fn words(text: &str) -> Vec<String> {
+    word_spans(text)
+        .into_iter()
+        .map(|span| text[span].to_lowercase())
+        .collect()
+}
+
+/// Byte ranges of the words of `text`.
+pub fn word_spans(text: &str) -> Vec<Range<usize>> {
+    let mut spans = vec![];
+    let mut start = None;
+    for (index, c) in text.char_indices() {
+        let word = c.is_alphanumeric() || matches!(c, '\'' | '*' | '#');
+        match (word, start) {
+            (true, None) => start = Some(index),
+            (false, Some(begin)) => {
+                spans.push(begin..index);
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    spans.extend(start.map(|begin| begin..text.len()));
+    spans
+}
+
+/// Lowercase words separated by single spaces.
+pub fn normalize(text: &str) -> String {
+    words(text).join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn grammar(rules: &str) -> Result<Grammar, String> {
+        let source = format!(
+            "<grammar xmlns=\"http://www.w3.org/2001/06/grammar\" root=\"main\">{}</grammar>",
+            rules
+        );
+        Grammar::compile("session:test", &source)
+    }
+
+    #[test]
+    fn interprets_phrases_with_tags() {
+        let grammar = grammar(
+            "<rule id=\"main\">I want <one-of>\
+             <item>coffee<tag>out=\"drink\";</tag></item>\
+             <item><ruleref uri=\"#size\"/> tea</item>\
+             </one-of></rule>\
+             <rule id=\"size\"><one-of><item>small</item><item>large</item></one-of></rule>",
+        )
+        .unwrap();
+        assert_eq!(
+            grammar.interpret("I want Coffee!"),
+            Some(String::from("drink"))
+        );
+        assert_eq!(
+            grammar.interpret("i want large tea"),
+            Some(String::from("i want large tea"))
+        );
+        assert_eq!(grammar.interpret("I want tea"), None);
+    }
+
+    #[test]
+    fn expands_repeats() {
+        let grammar = grammar(
+            "<rule id=\"main\"><item repeat=\"1-2\"><one-of><item>one</item>\
+             <item>two</item></one-of></item><item repeat=\"0-1\">please</item></rule>",
+        )
+        .unwrap();
+        assert_eq!(grammar.phrases().count(), 12);
+        assert!(grammar.interpret("two one please").is_some());
+        assert!(grammar.interpret("one two one").is_none());
+    }
+
+    #[test]
+    fn keeps_the_case_of_dtmf_keys() {
+        let source = "<grammar mode=\"dtmf\" root=\"main\"><rule id=\"main\">\
+                      <item repeat=\"2\"><one-of><item>1</item><item>A</item></one-of></item>\
+                      <item>#</item></rule></grammar>";
+        let grammar = Grammar::compile("session:keys", source).unwrap();
+        assert_eq!(grammar.mode, Mode::Dtmf);
+        assert_eq!(grammar.max_keys(), 3);
+        assert_eq!(grammar.interpret_keys("1A#"), Some(String::from("1 A #")));
+        assert_eq!(grammar.interpret_keys("1a#"), None);
+    }
+
+    #[test]
+    fn rejects_invalid_grammars() {
+        for rules in [
+            "<rule id=\"main\"><ruleref uri=\"#missing\"/></rule>",
+            "<rule id=\"main\"><ruleref uri=\"#main\"/></rule>",
+            "<rule id=\"main\"><ruleref uri=\"other.grxml#rule\"/></rule>",
+            "<rule id=\"main\"><item repeat=\"101\">a</item></rule>",
+            "<rule id=\"main\"><item repeat=\"3-2\">a</item></rule>",
+            "<rule id=\"main\"><item repeat=\"0-100\"><one-of><item>a</item>\
+             <item>b</item></one-of></item></rule>",
+        ] {
+            assert!(grammar(rules).is_err(), "{}", rules);
+        }
+        assert!(Grammar::compile("session:text", "yes\nno").is_err());
+        assert!(Grammar::compile("session:other", "<other/>").is_err());
+    }
+}
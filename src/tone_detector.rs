@@ -0,0 +1,224 @@
+const SAMPLE_RATE: f32 = 8000.0;
+const BLOCK_SIZE: usize = 205;
+const ROWS: [f32; 4] = [697.0, 770.0, 852.0, 941.0];
+const COLUMNS: [f32; 4] = [1209.0, 1336.0, 1477.0, 1633.0];
+const KEYS: [[char; 4]; 4] = [
+    ['1', '2', '3', 'A'],
+    ['4', '5', '6', 'B'],
+    ['7', '8', '9', 'C'],
+    ['*', '0', '#', 'D'],
+];
+/// Mean square of a block below which no tone is searched (about -40 dBFS).
+const MIN_ENERGY: f32 = 1e-4;
+/// Share of the block energy that must fall into the two DTMF frequencies.
+const MIN_TONE_RATIO: f32 = 0.3;
+/// Strongest frequency in a group must exceed the others by 6 dB.
+const MIN_DOMINANCE: f32 = 4.0;
+/// Allowed power ratio between the row and the column tone (8 dB).
+const MAX_TWIST: f32 = 6.3;
+/// Consecutive blocks a key must be heard in before it is reported.
+const MIN_BLOCKS: usize = 2;
+/// Bytes of audio the detector lags behind the start of a tone.
+pub const ONSET_BYTES: usize = MIN_BLOCKS * BLOCK_SIZE * 2;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct ToneDetection {
+    /// A DTMF tone is sounding in the current block.
+    pub tone: bool,
+    /// Key that has just been confirmed.
+    pub key: Option<char>,
+}
+
+/// Goertzel DTMF detector for 16-bit LPCM at 8 kHz.
+#[derive(Debug)]
+pub struct ToneDetector {
+    coefficients: [f32; 8],
+    samples: Vec<f32>,
+    candidate: Option<char>,
+    blocks: usize,
+    reported: bool,
+    tone: bool,
+}
+
+impl Default for ToneDetector {
+    fn default() -> Self {
+        let mut coefficients = [0.0; 8];
+        for (coefficient, frequency) in coefficients.iter_mut().zip(ROWS.iter().chain(&COLUMNS)) {
+            *coefficient = 2.0 * (2.0 * std::f32::consts::PI * frequency / SAMPLE_RATE).cos();
+        }
+        Self {
+            coefficients,
+            samples: Vec::with_capacity(BLOCK_SIZE),
+            candidate: None,
+            blocks: 0,
+            reported: false,
+            tone: false,
+        }
+    }
+}
+
+impl ToneDetector {
+    pub fn reset(&mut self) {
+        self.samples.clear();
+        self.candidate = None;
+        self.blocks = 0;
+        self.reported = false;
+        self.tone = false;
+    }
+
+    pub fn process(&mut self, frame: &[u8]) -> ToneDetection {
+        let mut detection = ToneDetection::default();
+        for sample in frame.chunks_exact(2) {
+            let sample = i16::from_le_bytes([sample[0], sample[1]]);
+            self.samples.push(sample as f32 / i16::MAX as f32);
+            if self.samples.len() == BLOCK_SIZE {
+                if let Some(key) = self.block() {
+                    detection.key = Some(key);
+                }
+                self.samples.clear();
+            }
+        }
+        detection.tone = self.tone;
+        detection
+    }
+
+    fn block(&mut self) -> Option<char> {
+        let key = self.analyze();
+        self.tone = key.is_some();
+        if key != self.candidate {
+            self.candidate = key;
+            self.blocks = 0;
+            self.reported = false;
+        }
+        key?;
+        self.blocks += 1;
+        if self.blocks >= MIN_BLOCKS && !self.reported {
+            self.reported = true;
+            return key;
+        }
+        None
+    }
+
+    fn analyze(&self) -> Option<char> {
+        let energy = self.samples.iter().map(|x| x * x).sum::<f32>();
+        if energy / (BLOCK_SIZE as f32) < MIN_ENERGY {
+            return None;
+        }
+        let mut powers = [0.0; 8];
+        for (power, coefficient) in powers.iter_mut().zip(self.coefficients) {
+            *power = goertzel(&self.samples, coefficient);
+        }
+        let (row, row_power) = dominant(&powers[..4])?;
+        let (column, column_power) = dominant(&powers[4..])?;
+        let twist = row_power / column_power;
+        if !(1.0 / MAX_TWIST..=MAX_TWIST).contains(&twist) {
+            return None;
+        }
+        let ratio = (row_power + column_power) / (energy * BLOCK_SIZE as f32);
+        (ratio >= MIN_TONE_RATIO).then_some(KEYS[row][column])
+    }
+}
+
+fn goertzel(samples: &[f32], coefficient: f32) -> f32 {
+    let (mut s1, mut s2) = (0.0, 0.0);
+    for sample in samples {
+        let s = sample + coefficient * s1 - s2;
+        s2 = s1;
+        s1 = s;
+    }
+    s1 * s1 + s2 * s2 - coefficient * s1 * s2
+}
+
+fn dominant(powers: &[f32]) -> Option<(usize, f32)> {
+    let (index, power) = powers
+        .iter()
+        .copied()
+        .enumerate()
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+    let dominates = powers
+        .iter()
+        .enumerate()
+        .all(|(other, other_power)| other == index || power >= MIN_DOMINANCE * other_power);
+    dominates.then_some((index, power))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dtmf::{DtmfCollector, DtmfEvent, DtmfParams};
+
+    /// Frames of 20 ms of the tone of `key`, or of silence.
+    fn frames(key: Option<char>, count: usize) -> Vec<Vec<u8>> {
+        let frequencies = key.and_then(|key| {
+            let row = KEYS.iter().position(|row| row.contains(&key))?;
+            let column = KEYS[row].iter().position(|other| *other == key)?;
+            Some((ROWS[row], COLUMNS[column]))
+        });
+        (0..count)
+            .map(|frame| {
+                (0..160)
+                    .flat_map(|i| {
+                        let t = (frame * 160 + i) as f32 / SAMPLE_RATE;
+                        let sample = frequencies.map_or(0.0, |(row, column)| {
+                            let tone = |f: f32| (2.0 * std::f32::consts::PI * f * t).sin();
+                            0.3 * (tone(row) + tone(column))
+                        });
+                        ((sample * i16::MAX as f32) as i16).to_le_bytes()
+                    })
+                    .collect()
+            })
+            .collect()
+    }
+
+    fn keys(detector: &mut ToneDetector, frames: &[Vec<u8>]) -> String {
+        frames
+            .iter()
+            .filter_map(|frame| detector.process(frame).key)
+            .collect()
+    }
+
+    #[test]
+    fn detects_every_key() {
+        for key in KEYS.iter().flatten() {
+            let mut detector = ToneDetector::default();
+            assert_eq!(keys(&mut detector, &frames(Some(*key), 5)), key.to_string());
+        }
+    }
+
+    #[test]
+    fn reports_a_held_key_once() {
+        let mut detector = ToneDetector::default();
+        let mut audio = frames(Some('5'), 10);
+        audio.extend(frames(None, 3));
+        audio.extend(frames(Some('5'), 5));
+        assert_eq!(keys(&mut detector, &audio), "55");
+    }
+
+    #[test]
+    fn ignores_silence_and_single_tones() {
+        let mut detector = ToneDetector::default();
+        assert_eq!(keys(&mut detector, &frames(None, 10)), "");
+        let hum = (0..1600)
+            .flat_map(|i| {
+                let t = i as f32 / SAMPLE_RATE;
+                ((0.5 * (2.0 * std::f32::consts::PI * 697.0 * t).sin() * i16::MAX as f32) as i16)
+                    .to_le_bytes()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(keys(&mut detector, &[hum]), "");
+    }
+
+    #[test]
+    fn tone_frame_raises_dtmf_input() {
+        let mut detector = ToneDetector::default();
+        let mut collector = DtmfCollector::default();
+        collector.prepare(vec![], DtmfParams::default(), true);
+        let mut events = vec![];
+        for frame in frames(Some('7'), 5) {
+            collector.frame(detector.process(&frame).key, 20);
+            events.push(collector.event());
+        }
+        assert!(events.contains(&DtmfEvent::Input));
+        assert_eq!(collector.digits(), "7");
+    }
+}
@@ -0,0 +1,157 @@
+use crate::config;
+use std::collections::HashMap;
+
+const ENERGY_THRESHOLD: &str = "energy-threshold";
+const PRE_ROLL: &str = "pre-roll";
+const MODEL: &str = "model";
+const PROFANITY_FILTER: &str = "profanity-filter";
+const PUNCTUATION: &str = "punctuation";
+
+/// Vendor-Specific-Parameters of SET-PARAMS and RECOGNIZE. The keys the
+/// engine does not know are passed through to the backend.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VendorParams {
+    /// Frame energy the speech detector takes for activity.
+    pub energy_threshold: Option<usize>,
+    /// Milliseconds of audio before the detected speech sent along with it.
+    pub pre_roll: Option<usize>,
+    pub model: Option<String>,
+    pub profanity_filter: Option<bool>,
+    pub punctuation: Option<bool>,
+    pub passthrough: HashMap<String, String>,
+}
+
+impl VendorParams {
+    /// Types the known keys of `params`. Those in `reserved` are handled by
+    /// the engine elsewhere and neither typed nor passed through.
+    pub fn parse(params: HashMap<String, String>, reserved: &[&str]) -> Self {
+        let mut typed = Self::default();
+        for (key, value) in params {
+            if reserved.contains(&key.as_str()) {
+                continue;
+            }
+            let valid = match key.as_str() {
+                ENERGY_THRESHOLD => parse(&mut typed.energy_threshold, &value, str::parse),
+                PRE_ROLL => parse(&mut typed.pre_roll, &value, |value| {
+                    value
+                        .parse::<usize>()
+                        .map(|duration| duration.min(config::MAX_PRE_ROLL))
+                }),
+                MODEL => parse(&mut typed.model, &value, |value| {
+                    Some(value.to_owned())
+                        .filter(|model| !model.is_empty())
+                        .ok_or(())
+                }),
+                PROFANITY_FILTER => parse(&mut typed.profanity_filter, &value, |value| {
+                    config::parse_bool(value).ok_or(())
+                }),
+                PUNCTUATION => parse(&mut typed.punctuation, &value, |value| {
+                    config::parse_bool(value).ok_or(())
+                }),
+                _ => {
+                    typed.passthrough.insert(key.clone(), value.clone());
+                    true
+                }
+            };
+            if !valid {
+                log::warn!("Invalid vendor-specific parameter {}={:?}", key, value);
+            }
+        }
+        typed
+    }
+
+    /// These parameters overridden by those `other` sets.
+    pub fn merged(&self, other: &Self) -> Self {
+        let mut passthrough = self.passthrough.clone();
+        passthrough.extend(other.passthrough.clone());
+        Self {
+            energy_threshold: other.energy_threshold.or(self.energy_threshold),
+            pre_roll: other.pre_roll.or(self.pre_roll),
+            model: other.model.clone().or_else(|| self.model.clone()),
+            profanity_filter: other.profanity_filter.or(self.profanity_filter),
+            punctuation: other.punctuation.or(self.punctuation),
+            passthrough,
+        }
+    }
+}
+
+fn parse<T, E>(
+    field: &mut Option<T>,
+    value: &str,
+    parse: impl FnOnce(&str) -> Result<T, E>,
+) -> bool {
+    match parse(value.trim()) {
+        Ok(value) => {
+            *field = Some(value);
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(pairs: &[(&str, &str)]) -> VendorParams {
+        let params = pairs
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        VendorParams::parse(params, &["dictation"])
+    }
+
+    #[test]
+    fn types_known_keys() {
+        let params = params(&[
+            ("energy-threshold", " 50 "),
+            ("pre-roll", "300"),
+            ("model", "phone"),
+            ("profanity-filter", "yes"),
+            ("punctuation", "off"),
+            ("dictation", "true"),
+            ("x-boost", "2"),
+        ]);
+        assert_eq!(params.energy_threshold, Some(50));
+        assert_eq!(params.pre_roll, Some(300));
+        assert_eq!(params.model.as_deref(), Some("phone"));
+        assert_eq!(params.profanity_filter, Some(true));
+        assert_eq!(params.punctuation, Some(false));
+        assert_eq!(
+            params.passthrough,
+            HashMap::from([(String::from("x-boost"), String::from("2"))])
+        );
+    }
+
+    #[test]
+    fn drops_invalid_values() {
+        let params = params(&[
+            ("energy-threshold", "-1"),
+            ("pre-roll", "soon"),
+            ("model", " "),
+            ("profanity-filter", "maybe"),
+            ("punctuation", ""),
+        ]);
+        assert_eq!(params, VendorParams::default());
+    }
+
+    #[test]
+    fn clamps_pre_roll() {
+        assert_eq!(
+            params(&[("pre-roll", "99999999999")]).pre_roll,
+            Some(config::MAX_PRE_ROLL)
+        );
+    }
+
+    #[test]
+    fn merges_overrides() {
+        let session = params(&[("pre-roll", "300"), ("model", "phone"), ("x-a", "1")]);
+        let request = params(&[("model", "video"), ("x-a", "2"), ("x-b", "3")]);
+        let merged = session.merged(&request);
+        assert_eq!(merged.pre_roll, Some(300));
+        assert_eq!(merged.model.as_deref(), Some("video"));
+        assert_eq!(merged.passthrough["x-a"], "2");
+        assert_eq!(merged.passthrough["x-b"], "3");
+        assert_eq!(session.merged(&VendorParams::default()), session);
+    }
+}
@@ -0,0 +1,241 @@
+use std::path::{Component, Path, PathBuf};
+
+pub const SAMPLE_RATE: u32 = 8000;
+const BYTES_PER_SAMPLE: u32 = 2;
+const WAV_HEADER_SIZE: usize = 44;
+
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum MediaType {
+    #[default]
+    Wav,
+    /// Headerless 16-bit little-endian LPCM.
+    Pcm,
+}
+
+impl MediaType {
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim().to_ascii_lowercase();
+        let essence = value.split(';').next().unwrap_or_default().trim();
+        match essence {
+            "" | "audio/wav" | "audio/x-wav" | "audio/wave" => Some(Self::Wav),
+            "audio/l16" | "audio/lpcm" | "audio/x-pcm" => Some(Self::Pcm),
+            _ => None,
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Wav => "wav",
+            Self::Pcm => "pcm",
+        }
+    }
+
+    pub fn encode(&self, pcm: &[u8]) -> Vec<u8> {
+        match self {
+            Self::Wav => wav(pcm),
+            Self::Pcm => pcm.to_vec(),
+        }
+    }
+}
+
+/// Recording of an utterance about to be saved.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Waveform {
+    pub path: PathBuf,
+    pub media_type: MediaType,
+}
+
+impl Waveform {
+    /// Unique file in `dir` for the request of a channel.
+    pub fn new(dir: &str, channel_id: &str, request_id: u32, media_type: MediaType) -> Self {
+        let channel_id = channel_id
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '-' })
+            .collect::<String>();
+        let name = format!("{}-{}.{}", channel_id, request_id, media_type.extension());
+        Self {
+            path: absolute(Path::new(dir)).join(name),
+            media_type,
+        }
+    }
+
+    /// File in `dir` named by a `file://` URI with a relative path. Paths
+    /// that are absolute or climb out of `dir` are refused.
+    pub fn from_uri(dir: &str, uri: &str, media_type: MediaType) -> Option<Self> {
+        let path = Path::new(uri.trim().strip_prefix("file://")?);
+        let mut components = path.components();
+        let relative = components.all(|component| matches!(component, Component::Normal(_)));
+        (relative && path.file_name().is_some()).then(|| Self {
+            path: absolute(Path::new(dir)).join(path),
+            media_type,
+        })
+    }
+
+    /// Value of the Waveform-URI header for `pcm` saved into this file.
+    pub fn header(&self, pcm: &[u8]) -> String {
+        let size = match self.media_type {
+            MediaType::Wav => pcm.len() + WAV_HEADER_SIZE,
+            MediaType::Pcm => pcm.len(),
+        };
+        format!(
+            "<file://{}>;size={};duration={}",
+            self.path.display(),
+            size,
+            duration(pcm)
+        )
+    }
+}
+
+/// Duration in milliseconds of 16-bit LPCM at 8 kHz.
+pub fn duration(pcm: &[u8]) -> usize {
+    pcm.len() * 1000 / (SAMPLE_RATE * BYTES_PER_SAMPLE) as usize
+}
+
+/// 16-bit LPCM at 8 kHz of a WAV file or of headerless audio.
+pub fn decode(data: Vec<u8>) -> Result<Vec<u8>, String> {
+    if !data.starts_with(b"RIFF") {
+        return Ok(data);
+    }
+    if data.len() < 12 || &data[8..12] != b"WAVE" {
+        return Err(String::from("RIFF file is not a WAV"));
+    }
+    let u16_at = |at: usize| u16::from_le_bytes([data[at], data[at + 1]]);
+    let u32_at =
+        |at: usize| u32::from_le_bytes([data[at], data[at + 1], data[at + 2], data[at + 3]]);
+    let mut format_checked = false;
+    let mut position = 12;
+    while position + 8 <= data.len() {
+        let size = u32_at(position + 4) as usize;
+        let body = position + 8;
+        match &data[position..position + 4] {
+            b"fmt " if size >= 16 && body + 16 <= data.len() => {
+                let format = (
+                    u16_at(body),
+                    u16_at(body + 2),
+                    u32_at(body + 4),
+                    u16_at(body + 14),
+                );
+                if format != (1, 1, SAMPLE_RATE, 8 * BYTES_PER_SAMPLE as u16) {
+                    return Err(format!(
+                        "Unsupported WAV format (format, channels, rate, bits) = {:?}",
+                        format
+                    ));
+                }
+                format_checked = true;
+            }
+            b"data" if format_checked => {
+                let end = body.saturating_add(size).min(data.len());
+                return Ok(data[body..end].to_vec());
+            }
+            b"data" => return Err(String::from("WAV data before format")),
+            _ => {}
+        }
+        position = body.saturating_add(size + size % 2);
+    }
+    Err(String::from("WAV without data"))
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        return path.to_owned();
+    }
+    std::env::current_dir()
+        .map(|dir| dir.join(path))
+        .unwrap_or_else(|_| path.to_owned())
+}
+
+fn wav(pcm: &[u8]) -> Vec<u8> {
+    let data_size = pcm.len() as u32;
+    let mut wav = Vec::with_capacity(WAV_HEADER_SIZE + pcm.len());
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_size).to_le_bytes());
+    wav.extend_from_slice(b"WAVEfmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes());
+    wav.extend_from_slice(&SAMPLE_RATE.to_le_bytes());
+    wav.extend_from_slice(&(SAMPLE_RATE * BYTES_PER_SAMPLE).to_le_bytes());
+    wav.extend_from_slice(&(BYTES_PER_SAMPLE as u16).to_le_bytes());
+    wav.extend_from_slice(&(8 * BYTES_PER_SAMPLE as u16).to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_size.to_le_bytes());
+    wav.extend_from_slice(pcm);
+    wav
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_wav_files() {
+        let pcm = vec![1, 0, 2, 0, 3, 0, 4, 0];
+        let wav = MediaType::Wav.encode(&pcm);
+        assert_eq!(wav.len(), WAV_HEADER_SIZE + pcm.len());
+        assert_eq!(&wav[..4], b"RIFF");
+        assert_eq!(&wav[4..8], &44u32.to_le_bytes());
+        assert_eq!(&wav[8..16], b"WAVEfmt ");
+        assert_eq!(&wav[24..28], &SAMPLE_RATE.to_le_bytes());
+        assert_eq!(&wav[36..40], b"data");
+        assert_eq!(&wav[40..44], &8u32.to_le_bytes());
+        assert_eq!(&wav[44..], &pcm[..]);
+        assert_eq!(MediaType::Pcm.encode(&pcm), pcm);
+    }
+
+    #[test]
+    fn parses_media_types() {
+        assert_eq!(MediaType::parse(""), Some(MediaType::Wav));
+        assert_eq!(MediaType::parse("Audio/X-Wav"), Some(MediaType::Wav));
+        assert_eq!(
+            MediaType::parse("audio/L16; rate=8000"),
+            Some(MediaType::Pcm)
+        );
+        assert_eq!(MediaType::parse("audio/mpeg"), None);
+    }
+
+    #[test]
+    fn names_files_inside_the_directory() {
+        let waveform = Waveform::new("/var/rec", "a1b2@speechrecog", 7, MediaType::Wav);
+        assert_eq!(waveform.path, Path::new("/var/rec/a1b2-speechrecog-7.wav"));
+        assert_eq!(
+            waveform.header(&[0; 16000]),
+            "<file:///var/rec/a1b2-speechrecog-7.wav>;size=16044;duration=1000"
+        );
+
+        let from_uri = |uri| Waveform::from_uri("/var/rec", uri, MediaType::Pcm);
+        let waveform = from_uri("file://calls/1.pcm").unwrap();
+        assert_eq!(waveform.path, Path::new("/var/rec/calls/1.pcm"));
+        assert_eq!(from_uri("file:///etc/passwd"), None);
+        assert_eq!(from_uri("file://../escape.pcm"), None);
+        assert_eq!(from_uri("file://calls/../../escape.pcm"), None);
+        assert_eq!(from_uri("http://host/1.pcm"), None);
+    }
+
+    #[test]
+    fn decodes_wav_and_raw_audio() {
+        let pcm = vec![1, 0, 2, 0, 3, 0];
+        assert_eq!(decode(MediaType::Wav.encode(&pcm)).unwrap(), pcm);
+        assert_eq!(decode(pcm.clone()).unwrap(), pcm);
+
+        // Chunks before the data are skipped, odd ones with their padding.
+        let mut wav = MediaType::Wav.encode(&pcm);
+        let list = [b"LIST".as_slice(), &3u32.to_le_bytes(), b"abc\0"].concat();
+        wav.splice(36..36, list);
+        assert_eq!(decode(wav).unwrap(), pcm);
+    }
+
+    #[test]
+    fn rejects_unsupported_wav() {
+        let mut stereo = MediaType::Wav.encode(&[0; 4]);
+        stereo[22] = 2;
+        assert!(decode(stereo).unwrap_err().contains("Unsupported"));
+        let mut wideband = MediaType::Wav.encode(&[0; 4]);
+        wideband[24..28].copy_from_slice(&16000u32.to_le_bytes());
+        assert!(decode(wideband).is_err());
+        assert!(decode(b"RIFF\0\0\0\0AVI ".to_vec()).is_err());
+        let wav = MediaType::Wav.encode(&[0; 4]);
+        assert!(decode(wav[..36].to_vec())
+            .unwrap_err()
+            .contains("without data"));
+    }
+}